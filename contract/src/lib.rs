@@ -2,7 +2,7 @@ use near_sdk::{
     borsh::{self, BorshSerialize, BorshDeserialize},
     store::{LookupMap, UnorderedSet, Vector},
     BorshStorageKey, AccountId, env,
-    near_bindgen, CryptoHash, assert_one_yocto, require,
+    near_bindgen, CryptoHash, assert_one_yocto,
 };
 use shared::types::SchemaId;
 
@@ -11,8 +11,13 @@ use shared::types::SchemaId;
 pub(crate) enum StorageKey {
     SchemasMap,
     SchemasVector { issuer_account_id_hash: CryptoHash },
+    // credential hashes of contracts deployed before registry roots, kept so storage prefixes don't collide
     CredentialsMap,
     CredentialsSet { issuer_account_id_hash: CryptoHash },
+    RootsMap,
+    RootsVector { issuer_account_id_hash: CryptoHash },
+    RevocationRootsMap,
+    RevocationRootsVector { issuer_account_id_hash: CryptoHash },
 }
 
 #[near_bindgen]
@@ -21,15 +26,26 @@ pub struct Contract {
     // CAUTION !! we use nested collections. See: https://docs.near.org/sdk/rust/contract-structure/nesting
     // Map: Issuer => Schemas
     schemas: LookupMap<AccountId, Vector<String>>,
-    // Map: Issuer => credential hashes
-    credentials: LookupMap<AccountId, UnorderedSet<String>>,
+    // Map: Issuer => Merkle roots of their credential hashes, one per published epoch
+    // Only roots are stored, so neither the chain nor RPC nodes learn which credentials get verified
+    roots: LookupMap<AccountId, Vector<String>>,
+    // Map: Issuer => (root of their sorted tree of revoked credential hashes, block timestamp it was published at)
+    revocation_roots: LookupMap<AccountId, Vector<(String, u64)>>,
+}
+
+// State of contracts deployed before registry roots, with a set of credential hashes per issuer
+#[derive(BorshDeserialize)]
+struct ContractV1 {
+    schemas: LookupMap<AccountId, Vector<String>>,
+    _credentials: LookupMap<AccountId, UnorderedSet<String>>,
 }
 
 impl Default for Contract {
     fn default() -> Self {
         Self {
             schemas: LookupMap::new(StorageKey::SchemasMap),
-            credentials: LookupMap::new(StorageKey::CredentialsMap),
+            roots: LookupMap::new(StorageKey::RootsMap),
+            revocation_roots: LookupMap::new(StorageKey::RevocationRootsMap),
         }
     }
 }
@@ -38,6 +54,20 @@ impl Default for Contract {
 #[near_bindgen]
 impl Contract {
 
+    /// Upgrades the state of a contract deployed before registry roots, keeping the issuers' schemas.
+    /// Call it right after deploying the new code to the same account. Issuers must then sync to publish their roots.
+    /// The old credential hashes stay in storage, as they can't be enumerated to be deleted
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old_state: ContractV1 = env::state_read().expect("no contract state to migrate");
+        Self {
+            schemas: old_state.schemas,
+            roots: LookupMap::new(StorageKey::RootsMap),
+            revocation_roots: LookupMap::new(StorageKey::RevocationRootsMap),
+        }
+    }
+
     /// Returns SchemaId of the added schema (which is also its index in the vector)
     /// CAUTION!! Schemas are add-only
     #[payable]
@@ -70,33 +100,59 @@ impl Contract {
         result
     }
 
-    /// Add and remove credential commitments
+    /// Publish the Merkle root of the issuer's current credential hashes, starting a new epoch.
+    /// Returns the epoch of the published root (which is also its index in the vector)
     #[payable]
-    pub fn modify_credentials(&mut self, remove: Vec<String>, add: Vec<String>) {
+    pub fn publish_root(&mut self, root: String) -> u32 {
         assert_one_yocto();
 
         let issuer = env::predecessor_account_id();
-        match self.credentials.get_mut(&issuer) {
-            // Issuer has committed credentials before 
-            Some(existing_credentials) => {
-                // Remove credentials
-                remove.iter().for_each(|credential| { existing_credentials.remove(credential); });
-                // Add the new credentials
-                existing_credentials.extend(add);
+        match self.roots.get_mut(&issuer) {
+            // Issuer has published roots before
+            Some(existing_roots) => {
+                existing_roots.push(root);
+                existing_roots.len() - 1
             },
-            // Issuer will commit credentials for the first time
+            // Issuer will publish their first root
             None => {
-                let mut new_set = UnorderedSet::new(StorageKey::CredentialsSet {
+                let mut new_vec = Vector::new(StorageKey::RootsVector {
                     issuer_account_id_hash: env::sha256_array(issuer.as_bytes()),
                 });
-                // Since we execute removals before additions, there is no point of running removals on an empty set
-                require!(remove.is_empty(), "Removals are not allowed in the first commitment");
-                // Add the new credentials
-                new_set.extend(add);
-                // Save the new schemas Vector into the Map: Issuer => schemas
-                self.credentials.set(issuer, Some(new_set));
+                new_vec.push(root);
+                // Save the new roots Vector into the Map: Issuer => roots
+                self.roots.set(issuer, Some(new_vec));
+                // The first root has epoch 0
+                0
             }
-        };
+        }
+    }
+
+    /// Publish the root of the issuer's sorted tree of revoked credential hashes.
+    /// Returns the epoch of the published root (which is also its index in the vector)
+    #[payable]
+    pub fn publish_revocation_root(&mut self, root: String) -> u32 {
+        assert_one_yocto();
+
+        let issuer = env::predecessor_account_id();
+        let entry = (root, env::block_timestamp());
+        match self.revocation_roots.get_mut(&issuer) {
+            // Issuer has published revocation roots before
+            Some(existing_roots) => {
+                existing_roots.push(entry);
+                existing_roots.len() - 1
+            },
+            // Issuer will publish their first revocation root
+            None => {
+                let mut new_vec = Vector::new(StorageKey::RevocationRootsVector {
+                    issuer_account_id_hash: env::sha256_array(issuer.as_bytes()),
+                });
+                new_vec.push(entry);
+                // Save the new roots Vector into the Map: Issuer => revocation roots
+                self.revocation_roots.set(issuer, Some(new_vec));
+                // The first root has epoch 0
+                0
+            }
+        }
     }
 
     /// Supports pagination using from and limit
@@ -128,33 +184,20 @@ impl Contract {
         result
     }
 
-    /// Supports pagination using from and limit
-    pub fn get_credentials(&self, issuer: AccountId, from: Option<u32>, limit: Option<u32>) -> Vec<String> {
-        let maybe_credentials = self.credentials.get(&issuer);
-        let result: Vec<String> = match maybe_credentials {
-            None => Vec::new(),
-            Some(all_credentials) => {
-                // If no start index specified, use 0
-                let inn_from = match from {
-                    Some(from_idx) => from_idx,
-                    _ => 0,
-                };
-                // If no limit specified, get all the issuer's schemas
-                let inn_limit = match limit {
-                    Some(chosen_limit) => chosen_limit,
-                    _ => all_credentials.len(),
-                };
-
-                all_credentials
-                    .iter()
-                    .skip(inn_from.try_into().unwrap())
-                    .take(inn_limit.try_into().unwrap())
-                    .cloned()
-                    .collect()
-            }
-        };
+    /// Returns the issuer's current (latest published) root, if any
+    pub fn get_root(&self, issuer: AccountId) -> Option<String> {
+        self.roots
+            .get(&issuer)
+            .and_then(|all_roots| all_roots.get(all_roots.len().checked_sub(1)?))
+            .cloned()
+    }
 
-        result
+    /// Returns the issuer's current (latest published) revocation root, if any
+    pub fn get_revocation_root(&self, issuer: AccountId) -> Option<String> {
+        self.revocation_roots
+            .get(&issuer)
+            .and_then(|all_roots| all_roots.get(all_roots.len().checked_sub(1)?))
+            .map(|(root, _)| root.clone())
     }
 
     /// Batch get schemas from different issuers.
@@ -180,16 +223,54 @@ impl Contract {
             .collect()
     }
 
-    /// Batch check the validity of credentials.
-    /// Accepts a vector of (issuer, credential hash) pairs.
-    /// Returns a vector containing the result of each check.
-    pub fn check_credentials(&self, pairs: Vec<(AccountId, String)>) -> Vec<bool> {
-        let results: Vec<bool> = pairs.
-            iter()
-            .map(|(issuer, credential_hash)| {
-                match self.credentials.get(issuer) {
+    /// Batch check the validity of credential registry roots.
+    /// Accepts a vector of (issuer, root) pairs.
+    /// Returns a vector containing the result of each check: whether the issuer ever published the root.
+    /// Old roots stay valid, as revoked credentials are caught by check_revocation_roots.
+    pub fn check_roots(&self, pairs: Vec<(AccountId, String)>) -> Vec<bool> {
+        let results: Vec<bool> = pairs
+            .iter()
+            .map(|(issuer, root)| {
+                match self.roots.get(issuer) {
+                    None => false,
+                    Some(all_roots) => all_roots.iter().any(|published_root| published_root == root),
+                }
+            })
+            .collect();
+
+        results
+    }
+
+    /// Batch check the validity of revocation roots.
+    /// Accepts a vector of (issuer, revocation root) pairs, and how long (in seconds) a superseded root stays valid.
+    /// Returns a vector containing the result of each check: whether the root is the issuer's current revocation root,
+    /// or was replaced at most max_age_secs ago.
+    pub fn check_revocation_roots(&self, pairs: Vec<(AccountId, String)>, max_age_secs: u64) -> Vec<bool> {
+        let now = env::block_timestamp();
+        let max_age = max_age_secs.saturating_mul(1_000_000_000);
+
+        let results: Vec<bool> = pairs
+            .iter()
+            .map(|(issuer, root)| {
+                match self.revocation_roots.get(issuer) {
                     None => false,
-                    Some(credentials) => credentials.contains(credential_hash),
+                    Some(all_roots) => {
+                        // walk back from the current root, while the roots were replaced recently enough
+                        let mut replaced_at: Option<u64> = None;
+                        all_roots
+                            .iter()
+                            .rev()
+                            .take_while(|(_, published_at)| {
+                                let is_recent = match replaced_at {
+                                    None => true,
+                                    Some(timestamp) => now.saturating_sub(timestamp) <= max_age,
+                                };
+                                // each root is replaced when the next one is published
+                                replaced_at = Some(*published_at);
+                                is_recent
+                            })
+                            .any(|(published_root, _)| published_root == root)
+                    },
                 }
             })
             .collect();
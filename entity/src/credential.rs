@@ -11,6 +11,7 @@ pub struct Model {
     pub holder_id: u32,
     pub schema_id: u32,
     pub details: String,
+    pub removed: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
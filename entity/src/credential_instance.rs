@@ -10,7 +10,10 @@ pub struct Model {
     pub id: u32,
     pub credential_id: u32,
     pub data: String,
-    pub hash: String
+    pub hash: String,
+    pub revoked: bool,
+    pub valid_from: i64,
+    pub valid_until: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -10,6 +10,8 @@ pub struct Model {
     pub id: u32,
     pub first_name: String,
     pub last_name: String,
+    pub commitment: Option<String>,
+    pub removed: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
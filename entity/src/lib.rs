@@ -5,3 +5,5 @@ pub mod prelude;
 pub mod credential;
 pub mod credential_instance;
 pub mod holder;
+pub mod nullifier;
+pub mod proof_job;
@@ -3,3 +3,5 @@
 pub use super::credential::Entity as Credential;
 pub use super::credential_instance::Entity as CredentialInstance;
 pub use super::holder::Entity as Holder;
+pub use super::nullifier::Entity as Nullifier;
+pub use super::proof_job::Entity as ProofJob;
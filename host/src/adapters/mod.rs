@@ -64,10 +64,10 @@ impl RegistryContract {
         ).await.unwrap();
     }
 
-    pub async fn get_credentials(&self) -> Vec<String> {
-        let result: Vec<String> = self.wallet.view(
+    pub async fn get_root(&self) -> Option<String> {
+        let result: Option<String> = self.wallet.view(
             &self.contract_address,
-            "get_credentials",
+            "get_root",
             json!({
                 "issuer": self.get_issuer_id()
             })
@@ -76,10 +76,10 @@ impl RegistryContract {
         result
     }
 
-    pub async fn check_credentials(&self, pairs: Vec<(String, String)>) -> Vec<bool> {
+    pub async fn check_roots(&self, pairs: Vec<(String, String)>) -> Vec<bool> {
         let result: Vec<bool> = self.wallet.view(
             &self.contract_address,
-            "check_credentials",
+            "check_roots",
             json!({
                 "pairs": pairs,
             })
@@ -88,14 +88,52 @@ impl RegistryContract {
         result
     }
 
-    pub async fn modify_credentials(&self, remove: Vec<String>, add: Vec<String>) {
+    pub async fn get_revocation_root(&self) -> Option<String> {
+        let result: Option<String> = self.wallet.view(
+            &self.contract_address,
+            "get_revocation_root",
+            json!({
+                "issuer": self.get_issuer_id()
+            })
+        ).await.unwrap();
+
+        result
+    }
+
+    pub async fn check_revocation_roots(&self, pairs: Vec<(String, String)>, max_age_secs: u64) -> Vec<bool> {
+        let result: Vec<bool> = self.wallet.view(
+            &self.contract_address,
+            "check_revocation_roots",
+            json!({
+                "pairs": pairs,
+                "max_age_secs": max_age_secs,
+            })
+        ).await.unwrap();
+
+        result
+    }
+
+    pub async fn publish_revocation_root(&self, root: String) {
+        self.wallet.tx(
+            &self.contract_address,
+            vec![FunctionCall(FunctionCallAction {
+                method_name: "publish_revocation_root".to_string(),
+                args: to_vec(&json!({
+                    "root": root,
+                })).unwrap(),
+                gas: 300_000_000_000_000,
+                deposit: 1,
+            })]
+        ).await.unwrap();
+    }
+
+    pub async fn publish_root(&self, root: String) {
         self.wallet.tx(
             &self.contract_address,
             vec![FunctionCall(FunctionCallAction {
-                method_name: "modify_credentials".to_string(),
+                method_name: "publish_root".to_string(),
                 args: to_vec(&json!({
-                    "remove": remove,
-                    "add": add,
+                    "root": root,
                 })).unwrap(),
                 gas: 300_000_000_000_000,
                 deposit: 1,
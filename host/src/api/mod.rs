@@ -19,7 +19,7 @@ pub async fn api_start(db_connection: DbConn, registry: RegistryContract) {
     let clonabe_registry = Arc::new(registry);
     let api_routes = Router::new()
         .nest("/hello", hello_router())
-        .nest("/holder", holder_router())
+        .nest("/holder", holder_router(db_connection.clone()))
         .nest("/issuer", issuer_router(db_connection.clone(), Arc::clone(&clonabe_registry)))
         .nest("/verifier", verifier_router(db_connection.clone(), Arc::clone(&clonabe_registry)));
 
@@ -30,7 +30,7 @@ pub async fn api_start(db_connection: DbConn, registry: RegistryContract) {
     println!("listening on {}", addr);
     
     let cors = CorsLayer::new()
-        .allow_methods(vec![Method::POST, Method::GET])
+        .allow_methods(vec![Method::POST, Method::GET, Method::DELETE])
         .allow_origin(Any)
         .allow_headers(Any);
     let app = Router::new()
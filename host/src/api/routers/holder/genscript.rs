@@ -4,7 +4,7 @@ use axum::{
 };
 use serde::{Serialize, Deserialize};
 use serde_json::to_string;
-use shared::types::ScriptLang;
+use shared::{types::ScriptLang, rhai_std::HELPERS_DOC};
 use async_openai::{
     types::{CreateChatCompletionRequestArgs, ChatCompletionRequestMessage, Role},
     Client,
@@ -74,9 +74,14 @@ pub async fn genscript_handler(Json(payload): Json<GenScriptArgs>) -> (StatusCod
             ```credentials = [{:?}]```.
             requirements:{}.
             Assume the "credentials" variable is defined and constant. Assume the objects in it are JSON parsed.
+            {}
             "#,
             payload.cred_schemes.join(","),
             &payload.requirements,
+            match payload.lang {
+                ScriptLang::Rhai => format!("You can use these helper functions:\n{}", HELPERS_DOC),
+                ScriptLang::JavaScript => "".to_string(),
+            },
         )),
         name: Option::None,
         function_call: Option::None
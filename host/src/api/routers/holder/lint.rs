@@ -0,0 +1,50 @@
+use axum::{
+    http::StatusCode,
+    Json,
+};
+use serde::{Serialize, Deserialize};
+use shared::{rhai_std, types::{ScriptLang, ScriptLimits, ScriptValue}};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+#[derive(Deserialize)]
+pub struct LintArgs {
+    // only Rhai scripts can be linted
+    lang: ScriptLang,
+    script: String,
+    // stringified credential details to run the script over, to also catch runtime errors (e.g. unknown functions)
+    #[serde(default)]
+    sample_credentials: Vec<String>,
+    #[serde(default)]
+    limits: ScriptLimits,
+}
+
+#[derive(Serialize)]
+pub struct LintResponse {
+    valid: bool,
+    error: Option<String>,
+    // output over the sample credentials, if any were given
+    output: Option<ScriptValue>,
+}
+
+// check a Rhai script with the same engine and helpers as the guest, before proving with it
+pub async fn lint_handler(Json(payload): Json<LintArgs>) -> (StatusCode, Json<LintResponse>) {
+    // the guest's JavaScript engine is not available on the host
+    if !matches!(payload.lang, ScriptLang::Rhai) {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(LintResponse { valid: false, error: Some("only Rhai scripts can be linted".to_string()), output: None }),
+        );
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("system time is before the UNIX epoch").as_secs();
+
+    let response = match rhai_std::engine(now, &payload.limits).compile(&payload.script) {
+        Err(error) => LintResponse { valid: false, error: Some(error.to_string()), output: None },
+        Ok(_) if payload.sample_credentials.is_empty() => LintResponse { valid: true, error: None, output: None },
+        Ok(_) => match rhai_std::eval(&payload.script, &payload.sample_credentials, now, &payload.limits) {
+            Ok(output) => LintResponse { valid: true, error: None, output: Some(output) },
+            Err(error) => LintResponse { valid: false, error: Some(error), output: None },
+        },
+    };
+    (StatusCode::OK, Json(response))
+}
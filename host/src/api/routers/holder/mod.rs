@@ -1,13 +1,17 @@
 mod proof;
 mod genscript;
+mod lint;
 
 use axum::routing::{Router, post};
+use sea_orm::DbConn;
 use proof::proof_router;
 use genscript::genscript_handler;
+use lint::lint_handler;
 
 
-pub fn holder_router() -> Router {
+pub fn holder_router(db_connection: DbConn) -> Router {
     Router::new()
-        .nest("/proof", proof_router())
+        .nest("/proof", proof_router(db_connection))
         .route("/genscript", post(genscript_handler))
+        .route("/lint", post(lint_handler))
 }
@@ -0,0 +1,375 @@
+mod queue;
+mod prover;
+
+use entity::proof_job::{self, JobState};
+use shared::{
+    types::{ScriptLang, ScriptLimits, ZkCommit, ZkvmInput, Challenge, ReceiptKind},
+    merkle::{MerklePath, NonMembershipProof},
+};
+use sea_orm::{DbConn, EntityTrait, QueryFilter, QueryOrder, ColumnTrait, Condition};
+use axum::{
+    routing::{Router, post, get, delete},
+    extract::{State, Path},
+    response::sse::{Event, KeepAlive, Sse},
+    Json, http::StatusCode
+};
+use serde::{Serialize, Deserialize};
+use base64ct::{Base64, Encoding};
+use futures::stream::Stream;
+use risc0_zkvm::Receipt;
+use methods::ZK_PROVER_ID;
+use sha2::{Sha256, Digest};
+use serde_json::to_vec;
+use tokio::sync::broadcast::error::RecvError;
+use crate::{config, journal::decode_journal};
+use queue::{ProofQueue, ProofStage, WorkerState, now_secs};
+
+
+#[derive(Serialize)]
+pub struct GenProofResponse {
+    task_id: u32,
+    active_tasks: Vec<u32>,
+}
+
+#[derive(Serialize, Clone)]
+pub enum GetStatusResponse {
+    Pending(PendingProofResponse),
+    Ready(ReadyProofResponse),
+    Failed(String),
+    Unknown,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PendingProofResponse {
+    active_tasks: Vec<u32>,
+    time_estimate_minutes: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ReadyProofResponse {
+    // Base64 encoded bincode of the risc0 Receipt, as expected by verifiers
+    proof: String,
+    journal: Option<ZkCommit>,
+    receipt_kind: Option<ReceiptKind>,
+    receipt_size: usize,
+    cycles: Option<i64>,
+    prove_ms: Option<i64>,
+    verify_ms: Option<i64>,
+}
+
+// proof job, without its input (which contains the holder secret) and receipt
+#[derive(Serialize)]
+pub struct JobInfo {
+    task_id: u32,
+    state: JobState,
+    created_at: i64,
+    started_at: Option<i64>,
+    finished_at: Option<i64>,
+    cycles: Option<i64>,
+    prove_ms: Option<i64>,
+    verify_ms: Option<i64>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PreviewResponse {
+    journal: Option<ZkCommit>,
+    cycles: u64,
+    segments: usize,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GenProofArgs {
+    // Use stringified credential instance to get the same instance hash from within zkvm
+    credentials: Vec<String>,
+    // inclusion proof of each credential in its issuer's registry, obtained from the issuer
+    cred_paths: Vec<MerklePath>,
+    // proof that each credential is not revoked by its issuer, obtained from the issuer
+    cred_revocations: Vec<NonMembershipProof>,
+    // JSON Schema of each credential, as registered by its issuer on the registry contract
+    cred_schema_docs: Vec<String>,
+    lang: ScriptLang,
+    script: String,
+    // defaults to the limits of the script language's engine
+    #[serde(default)]
+    limits: Option<ScriptLimits>,
+    // JSON pointers of the fields to disclose verbatim to the verifier, per credential
+    #[serde(default)]
+    disclose: Vec<Vec<String>>,
+    // only commit the script hash, the script must then be sent to the verifier along with the proof
+    #[serde(default)]
+    script_hash_only: bool,
+    // challenge obtained from the verifier this proof will be presented to
+    challenge: Challenge,
+    // secret whose hash the issuer embedded in the credentials
+    holder_secret: String,
+    // succinct receipts are much smaller to present, but take longer to prove
+    #[serde(default)]
+    receipt_kind: ReceiptKind,
+    // always prove, instead of returning a previous job for the same input
+    #[serde(default)]
+    fresh: bool,
+}
+
+impl GenProofArgs {
+    pub fn zkvm_input(&self) -> ZkvmInput {
+        ZkvmInput {
+            credentials: self.credentials.clone(),
+            cred_paths: self.cred_paths.clone(),
+            cred_revocations: self.cred_revocations.clone(),
+            cred_schema_docs: self.cred_schema_docs.clone(),
+            lang: self.lang,
+            script: self.script.clone(),
+            limits: self.limits.unwrap_or_else(|| ScriptLimits::default_for(self.lang)),
+            disclose: self.disclose.clone(),
+            script_hash_only: self.script_hash_only,
+            challenge: self.challenge.clone(),
+            holder_secret: self.holder_secret.clone(),
+        }
+    }
+
+    /// Base64 encoded hash of everything that determines the receipt: guest image, input, receipt kind and dev mode
+    pub fn input_digest(&self) -> String {
+        let image_id: Vec<u8> = ZK_PROVER_ID.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let digest = Sha256::new()
+            .chain_update(image_id)
+            .chain_update(to_vec(&self.zkvm_input()).unwrap())
+            .chain_update(to_vec(&self.receipt_kind).unwrap())
+            // fake receipts proven in dev mode must not be returned once it is disabled
+            .chain_update([config::dev_mode() as u8])
+            .finalize();
+
+        Base64::encode_string(&digest)
+    }
+}
+
+pub type AppState = ProofQueue;
+
+
+pub fn proof_router(db_connection: DbConn) -> Router {
+    let state = ProofQueue::start(db_connection);
+
+    Router::new()
+        .route("/", get(list_handler))
+        .route("/:task_id", delete(cancel_handler))
+        .route("/generate", post(genproof_handler))
+        .route("/preview", post(preview_handler))
+        .route("/status", get(workers_handler))
+        .route("/status/:task_id", get(status_handler))
+        .route("/status/:task_id/events", get(events_handler))
+        .with_state(state)
+}
+
+async fn genproof_handler(
+    State(app_state): State<AppState>,
+    Json(payload): Json<GenProofArgs>
+) -> (StatusCode, Json<GenProofResponse>) {
+    // Return the job that proves (or proved) the same input, if any
+    if !payload.fresh {
+        if let Some(task_id) = app_state.find_cached(&payload.input_digest()).await {
+            return (
+                StatusCode::ACCEPTED,
+                Json(GenProofResponse { task_id, active_tasks: app_state.active_tasks() })
+            );
+        }
+    }
+
+    // Persist the job first, so its dry-run can be followed
+    let task_id = app_state.push(&payload).await;
+
+    // Run the guest without proving, to know how many cycles (and segments) the proof will take
+    let input = payload.zkvm_input();
+    let dry_run = tokio::task::spawn_blocking(move || prover::execute(&input))
+        .await
+        .unwrap_or_else(|err| Err(format!("executor crashed: {}", err)))
+        .map(|execution| (execution.cycles, execution.segments));
+
+    // Jobs over the cycle budget (or failing otherwise) are saved as failed, with the reason
+    let status_code = if dry_run.is_ok() { StatusCode::ACCEPTED } else { StatusCode::UNPROCESSABLE_ENTITY };
+
+    // Queue the job, a worker picks it up from there
+    app_state.finish_dry_run(task_id, dry_run).await;
+
+    (
+        status_code,
+        Json(GenProofResponse { task_id, active_tasks: app_state.active_tasks() })
+    )
+}
+
+// Run the guest in the executor only, so holders can check the script's result before waiting for a proof
+async fn preview_handler(Json(payload): Json<GenProofArgs>) -> (StatusCode, Json<PreviewResponse>) {
+    let input = payload.zkvm_input();
+    let execution = tokio::task::spawn_blocking(move || prover::execute(&input))
+        .await
+        .unwrap_or_else(|err| Err(format!("executor crashed: {}", err)));
+
+    let response = match execution {
+        Ok(execution) => match decode_journal(&execution.journal.bytes) {
+            Ok(journal) => PreviewResponse {
+                journal: Some(journal),
+                cycles: execution.cycles,
+                segments: execution.segments,
+                error: None,
+            },
+            Err(error) => PreviewResponse { journal: None, cycles: execution.cycles, segments: execution.segments, error: Some(error) },
+        },
+        Err(error) => PreviewResponse { journal: None, cycles: 0, segments: 0, error: Some(error) },
+    };
+    (StatusCode::ACCEPTED, Json(response))
+}
+
+pub async fn status_handler(State(app_state): State<AppState>, Path(task_id): Path<u32>) -> (StatusCode, Json<GetStatusResponse>) {
+    let maybe_job = proof_job::Entity::find_by_id(task_id)
+        .one(app_state.db())
+        .await.expect("failed to get proof job from DB");
+
+    let (status_code, response) = match maybe_job {
+        None => (StatusCode::NOT_FOUND, GetStatusResponse::Unknown),
+        Some(job) => match job.state {
+            JobState::Ready => {
+                let receipt_bytes = job.receipt.unwrap_or_default();
+                let receipt = bincode::deserialize::<Receipt>(&receipt_bytes).ok();
+                let journal = receipt.as_ref().and_then(|receipt| decode_journal(&receipt.journal.bytes).ok());
+                let receipt_kind = receipt.as_ref().and_then(prover::receipt_kind);
+
+                (
+                    StatusCode::OK,
+                    GetStatusResponse::Ready(ReadyProofResponse {
+                        proof: Base64::encode_string(&receipt_bytes),
+                        journal,
+                        receipt_kind,
+                        receipt_size: receipt_bytes.len(),
+                        cycles: job.cycles,
+                        prove_ms: job.prove_ms,
+                        verify_ms: job.verify_ms,
+                    })
+                )
+            },
+            JobState::Failed => (StatusCode::OK, GetStatusResponse::Failed(job.error.unwrap_or_default())),
+            JobState::Executing | JobState::Proving | JobState::Queued => {
+                let cycles_per_sec = app_state.cycles_per_sec().await;
+                // jobs being proven, and the ones that will be picked by a worker before this one
+                let jobs_ahead = proof_job::Entity::find()
+                    .filter(
+                        Condition::any()
+                            .add(proof_job::Column::State.eq(JobState::Proving))
+                            .add(
+                                Condition::all()
+                                    .add(proof_job::Column::State.eq(JobState::Queued))
+                                    .add(proof_job::Column::Id.lte(task_id))
+                            )
+                    )
+                    .all(app_state.db())
+                    .await.expect("failed to get pending proof jobs from DB");
+
+                let now = now_secs();
+                // cycles left to prove, assuming proofs started so far progress at the measured speed
+                let remaining_cycles = |job: &proof_job::Model| {
+                    let cycles = job.cycles.unwrap_or_default() as f64;
+                    let proven = job.started_at.map_or(0.0, |started_at| (now - started_at) as f64 * cycles_per_sec);
+                    (cycles - proven).max(0.0)
+                };
+
+                let secs = if job.state == JobState::Proving {
+                    remaining_cycles(&job) / cycles_per_sec
+                }
+                else {
+                    // all workers share the cycles ahead of this job, then one of them proves it
+                    let cycles_ahead: f64 = jobs_ahead
+                        .iter()
+                        .filter(|job_ahead| job_ahead.id != task_id)
+                        .map(remaining_cycles)
+                        .sum();
+                    let num_workers = app_state.workers().len() as f64;
+                    (cycles_ahead / num_workers + remaining_cycles(&job)) / cycles_per_sec
+                };
+
+                (
+                    StatusCode::ACCEPTED,
+                    GetStatusResponse::Pending(PendingProofResponse {
+                        active_tasks: app_state.active_tasks(),
+                        time_estimate_minutes: (secs / 60.0).ceil() as u64,
+                    })
+                )
+            },
+        },
+    };
+    (status_code, Json(response))
+}
+
+// Stream the stages of a proof job as Server-Sent Events, until it is ready, failed or cancelled
+pub async fn events_handler(
+    State(app_state): State<AppState>,
+    Path(task_id): Path<u32>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    // subscribe before reading the current stage, so no transition is missed
+    let mut events = app_state.subscribe();
+
+    let current_stage = app_state.current_stage(task_id).await.ok_or(StatusCode::NOT_FOUND)?;
+
+    let stream = async_stream::stream! {
+        let mut stage = current_stage;
+        yield Event::default().json_data(&stage);
+
+        while !stage.is_final() {
+            match events.recv().await {
+                Ok(event) if event.task_id == task_id => {
+                    stage = event.stage;
+                    yield Event::default().json_data(&stage);
+                },
+                // transitions of other jobs
+                Ok(_) => continue,
+                // some transitions were missed, possibly the final one: catch up with the job
+                Err(RecvError::Lagged(_)) => {
+                    let latest_stage = app_state.current_stage(task_id).await.unwrap_or(ProofStage::Cancelled);
+                    if latest_stage != stage {
+                        stage = latest_stage;
+                        yield Event::default().json_data(&stage);
+                    }
+                },
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// list all proof jobs, newest first
+pub async fn list_handler(State(app_state): State<AppState>) -> (StatusCode, Json<Vec<JobInfo>>) {
+    let jobs = proof_job::Entity::find()
+        .order_by_desc(proof_job::Column::Id)
+        .all(app_state.db())
+        .await.expect("failed to get proof jobs from DB")
+        .into_iter()
+        .map(|job| JobInfo {
+            task_id: job.id,
+            state: job.state,
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            cycles: job.cycles,
+            prove_ms: job.prove_ms,
+            verify_ms: job.verify_ms,
+            error: job.error,
+        })
+        .collect();
+
+    (StatusCode::ACCEPTED, Json(jobs))
+}
+
+// delete a proof job, whether it is queued, being proven or finished
+pub async fn cancel_handler(State(app_state): State<AppState>, Path(task_id): Path<u32>) -> (StatusCode, Json<bool>) {
+    if app_state.cancel(task_id).await {
+        (StatusCode::ACCEPTED, Json(true))
+    }
+    else {
+        (StatusCode::NOT_FOUND, Json(false))
+    }
+}
+
+// state of each proving worker
+pub async fn workers_handler(State(app_state): State<AppState>) -> (StatusCode, Json<Vec<WorkerState>>) {
+    (StatusCode::ACCEPTED, Json(app_state.workers()))
+}
@@ -0,0 +1,221 @@
+use methods::{ZK_PROVER_ELF, ZK_PROVER_ID};
+use shared::types::{ZkCommit, ZkvmInput, ReceiptKind, RemoteProofRequest, RemoteProofResponse};
+use risc0_zkvm::{
+    ExecutorEnv, Receipt, InnerReceipt, Journal, default_prover, default_executor,
+    recursion::{lift, join},
+    serde::to_vec,
+};
+use std::{
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    time::Instant,
+};
+use futures::future::{BoxFuture, FutureExt};
+use serde_json::to_string;
+use base64ct::{Base64, Encoding};
+
+use crate::journal::decode_journal;
+
+
+pub const CANCELLED: &str = "proof job was cancelled";
+
+// Outcome of running the guest without proving
+pub struct Execution {
+    // cycles to be proven, i.e. the sum of each segment's (padded) size
+    pub cycles: u64,
+    pub segments: usize,
+    pub journal: Journal,
+}
+
+/// Maximum number of cycles a proof may take, read from MAX_PROOF_CYCLES (unlimited if not set)
+pub fn cycle_budget() -> Option<u64> {
+    std::env::var("MAX_PROOF_CYCLES")
+        .ok()
+        .map(|value| value.parse().expect("MAX_PROOF_CYCLES must be a positive integer."))
+}
+
+// Run the guest in the executor only, which is orders of magnitude faster than proving.
+// Fails if the execution exceeds the cycle budget
+pub fn execute(input: &ZkvmInput) -> Result<Execution, String> {
+    let budget = cycle_budget();
+    let env = ExecutorEnv::builder()
+        .write(input)
+        .map_err(|err| err.to_string())?
+        // stop runaway executions early, instead of after exhausting the machine
+        .session_limit(budget)
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let session = default_executor()
+        .execute_elf(env, ZK_PROVER_ELF)
+        .map_err(|err| format!("execution failed: {}", err))?;
+
+    let execution = Execution {
+        cycles: session.segments.iter().map(|segment| 1u64 << segment.po2).sum(),
+        segments: session.segments.len(),
+        journal: session.journal,
+    };
+    match budget {
+        Some(max_cycles) if execution.cycles > max_cycles => Err(format!(
+            "proof would take {} cycles, over the budget of {} cycles", execution.cycles, max_cycles
+        )),
+        _ => Ok(execution),
+    }
+}
+
+// Where receipts are produced. `cancelled` is set when the job being proven is cancelled
+pub trait ProverBackend: Send + Sync {
+    // Returns the receipt and how long proving took, in milliseconds
+    fn prove(&self, input: ZkvmInput, kind: ReceiptKind, cancelled: Arc<AtomicBool>) -> BoxFuture<'static, Result<(Receipt, u64), String>>;
+
+    // Whether dropping the future returned by `prove` stops the proof
+    fn stops_on_drop(&self) -> bool {
+        true
+    }
+}
+
+// Proves on this machine
+pub struct LocalProver;
+
+impl ProverBackend for LocalProver {
+    fn prove(&self, input: ZkvmInput, kind: ReceiptKind, cancelled: Arc<AtomicBool>) -> BoxFuture<'static, Result<(Receipt, u64), String>> {
+        // proving takes minutes of CPU time, keep it off the async runtime
+        tokio::task::spawn_blocking(move || prove(&input, kind, &cancelled))
+            .map(|proof| proof.unwrap_or_else(|err| Err(format!("prover crashed: {}", err))))
+            .boxed()
+    }
+
+    // blocking tasks can't be aborted, the proof only stops once it checks `cancelled`
+    fn stops_on_drop(&self) -> bool {
+        false
+    }
+}
+
+// Offloads proving to a remote proving service, which receives RemoteProofRequests on POST {url}/prove
+pub struct RemoteProver {
+    url: String,
+}
+
+impl ProverBackend for RemoteProver {
+    // the request is dropped along with the future, when the job is cancelled
+    fn prove(&self, input: ZkvmInput, kind: ReceiptKind, _cancelled: Arc<AtomicBool>) -> BoxFuture<'static, Result<(Receipt, u64), String>> {
+        let url = format!("{}/prove", self.url);
+        async move {
+            // proofs take minutes, so requests must not time out (which is the default)
+            let request = RemoteProofRequest { input, receipt_kind: kind };
+            let response: RemoteProofResponse = reqwest::Client::new()
+                .post(url)
+                .json(&request)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|err| format!("remote prover error: {}", err))?
+                .json()
+                .await
+                .map_err(|err| format!("remote prover error: {}", err))?;
+
+            match (response.base64_receipt, response.error) {
+                (_, Some(error)) => Err(error),
+                (Some(base64_receipt), None) => {
+                    let receipt_bytes = Base64::decode_vec(&base64_receipt).map_err(|err| err.to_string())?;
+                    let receipt = bincode::deserialize(&receipt_bytes).map_err(|err| err.to_string())?;
+                    Ok((receipt, response.prove_ms))
+                },
+                (None, None) => Err("remote prover returned no receipt".to_string()),
+            }
+        }.boxed()
+    }
+}
+
+/// Prover backend selected by PROVER_BACKEND, either "local" (the default) or "remote".
+/// The remote proving service is reached at REMOTE_PROVER_URL
+pub fn backend_from_env() -> Arc<dyn ProverBackend> {
+    match std::env::var("PROVER_BACKEND").as_deref() {
+        Err(_) | Ok("local") => Arc::new(LocalProver),
+        Ok("remote") => Arc::new(RemoteProver {
+            url: std::env::var("REMOTE_PROVER_URL").expect("REMOTE_PROVER_URL must be set."),
+        }),
+        Ok(_) => panic!("PROVER_BACKEND must be either \"local\" or \"remote\"."),
+    }
+}
+
+// Returns the receipt and how long proving took, in milliseconds.
+// Stops between proving steps once `cancelled` is set
+pub fn prove(input: &ZkvmInput, kind: ReceiptKind, cancelled: &AtomicBool) -> Result<(Receipt, u64), String> {
+    if cancelled.load(Ordering::Relaxed) {
+        return Err(CANCELLED.to_string());
+    }
+
+    // First, we construct an executor environment
+    let env = ExecutorEnv::builder()
+        .write(input)
+        .map_err(|err| err.to_string())?
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    // Obtain the local prover.
+    let prover = default_prover();
+
+    let start_time_prover = Instant::now();
+
+    // Produce a receipt by proving the specified ELF binary.
+    let receipt = prover
+        .prove_elf(env, ZK_PROVER_ELF)
+        .map_err(|err| err.to_string())?;
+
+    let receipt = match kind {
+        // fake receipts (dev mode) cannot be compressed
+        _ if matches!(receipt.inner, InnerReceipt::Fake) => receipt,
+        ReceiptKind::Composite => receipt,
+        ReceiptKind::Succinct => compress(receipt, cancelled)?,
+    };
+
+    let prove_duration = start_time_prover.elapsed();
+    println!("Prover duration {:?}", prove_duration);
+    println!("Receipt size {:.2} (KB)", (to_vec(&receipt).unwrap().len() / 1024));
+
+    Ok((receipt, prove_duration.as_millis() as u64))
+}
+
+// Aggregate the segment receipts into a single succinct receipt, using recursion
+fn compress(receipt: Receipt, cancelled: &AtomicBool) -> Result<Receipt, String> {
+    let segments = match &receipt.inner {
+        InnerReceipt::Flat(segment_receipts) => &segment_receipts.0,
+        _ => return Err("only composite receipts can be compressed".to_string()),
+    };
+
+    let (first, rest) = segments.split_first().ok_or("receipt has no segments".to_string())?;
+    let mut succinct = lift(first).map_err(|err| err.to_string())?;
+    for segment in rest {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(CANCELLED.to_string());
+        }
+        let lifted = lift(segment).map_err(|err| err.to_string())?;
+        succinct = join(&succinct, &lifted).map_err(|err| err.to_string())?;
+    }
+
+    Ok(Receipt::new(InnerReceipt::Succinct(succinct), receipt.journal.bytes))
+}
+
+pub fn receipt_kind(receipt: &Receipt) -> Option<ReceiptKind> {
+    match receipt.inner {
+        // fake receipts (dev mode) are produced in place of composite ones
+        InnerReceipt::Flat(_) | InnerReceipt::Fake => Some(ReceiptKind::Composite),
+        InnerReceipt::Succinct(_) => Some(ReceiptKind::Succinct),
+        _ => None,
+    }
+}
+
+// Verify the receipt to confirm that recipients will also be able to verify it.
+// Returns how long verification took, in milliseconds
+pub fn verify(receipt: &Receipt) -> Result<u64, String> {
+    // Get guest result
+    let code_result: ZkCommit = decode_journal(&receipt.journal.bytes)?;
+    println!("Result: {:?}", to_string(&code_result));
+
+    let start_time_verifier = Instant::now();
+    receipt.verify(ZK_PROVER_ID).map_err(|err| err.to_string())?;
+    let verify_duration = start_time_verifier.elapsed();
+    println!("Verifier duration {:?}", verify_duration);
+
+    Ok(verify_duration.as_millis() as u64)
+}
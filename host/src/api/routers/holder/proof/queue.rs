@@ -0,0 +1,488 @@
+use entity::proof_job::{self, JobState};
+use sea_orm::{
+    DbConn, EntityTrait, QueryFilter, QueryOrder, QuerySelect, ColumnTrait, Condition, Set,
+    sea_query::Expr,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{Notify, broadcast};
+use serde::Serialize;
+use risc0_zkvm::Receipt;
+use serde_json::{from_str, to_string};
+
+use super::{GenProofArgs, prover::{self, ProverBackend}};
+
+
+pub fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("system time is before the UNIX epoch").as_secs() as i64
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+pub enum WorkerState {
+    Idle,
+    Proving { task_id: u32 },
+}
+
+// Stage of a proof job, as pushed to clients following its progress
+#[derive(Serialize, Clone, PartialEq)]
+pub enum ProofStage {
+    // dry-run of the guest, to count its cycles and segments before queueing the job
+    Executing,
+    Queued,
+    // estimated from the measured proving speed, as the prover does not report progress
+    Proving { segment: usize, segments: usize },
+    Verifying,
+    Ready,
+    Failed(String),
+    Cancelled,
+}
+
+impl ProofStage {
+    pub fn is_final(&self) -> bool {
+        matches!(self, ProofStage::Ready | ProofStage::Failed(_) | ProofStage::Cancelled)
+    }
+}
+
+#[derive(Clone)]
+pub struct ProofEvent {
+    pub task_id: u32,
+    pub stage: ProofStage,
+}
+
+// Cancellation signal of a job being proven
+#[derive(Default)]
+struct Cancellation {
+    // checked by the local prover between proving steps
+    flag: Arc<AtomicBool>,
+    // wakes the worker up, so it stops waiting on proofs that can be dropped
+    notify: Notify,
+}
+
+// Assumed proving speed until this machine has proven some jobs
+const DEFAULT_CYCLES_PER_SEC: f64 = 20_000.0;
+// Number of recent jobs the proving speed is measured over
+const SPEED_SAMPLE_SIZE: u64 = 20;
+// How often finished jobs older than PROOF_RESULT_TTL_SECS are purged
+const PURGE_INTERVAL_SECS: u64 = 60;
+
+// Proof jobs are persisted in the proof_job table, so queued and finished proofs survive restarts
+#[derive(Clone)]
+pub struct ProofQueue {
+    db_connection: DbConn,
+    // wakes an idle worker up when a job is queued
+    notify: Arc<Notify>,
+    // state of each worker, indexed by worker ID
+    workers: Arc<Mutex<Vec<WorkerState>>>,
+    // stage transitions of all jobs
+    events: broadcast::Sender<ProofEvent>,
+    // latest stage of the jobs being proven, which is not persisted
+    stages: Arc<Mutex<HashMap<u32, ProofStage>>>,
+    // produces the receipts, locally or on a remote proving service
+    backend: Arc<dyn ProverBackend>,
+    // cancellation signals of the jobs being proven
+    cancellations: Arc<Mutex<HashMap<u32, Arc<Cancellation>>>>,
+}
+
+impl ProofQueue {
+    /// Creates the queue and spawns its workers.
+    /// The number of workers (i.e. concurrent proofs) is read from PROVER_WORKERS, and defaults to 1.
+    /// Finished jobs are deleted PROOF_RESULT_TTL_SECS after they finish (1 day by default)
+    pub fn start(db_connection: DbConn) -> Self {
+        let num_workers: usize = match std::env::var("PROVER_WORKERS") {
+            Ok(value) => value.parse().expect("PROVER_WORKERS must be a positive integer."),
+            Err(_) => 1,
+        };
+        assert!(num_workers > 0, "PROVER_WORKERS must be a positive integer.");
+        let result_ttl_secs: i64 = match std::env::var("PROOF_RESULT_TTL_SECS") {
+            Ok(value) => value.parse().expect("PROOF_RESULT_TTL_SECS must be an integer."),
+            Err(_) => 24 * 60 * 60,
+        };
+
+        let queue = Self {
+            db_connection,
+            notify: Arc::new(Notify::new()),
+            workers: Arc::new(Mutex::new(vec![WorkerState::Idle; num_workers])),
+            events: broadcast::channel(256).0,
+            stages: Arc::new(Mutex::new(HashMap::new())),
+            backend: prover::backend_from_env(),
+            cancellations: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        let starter = queue.clone();
+        tokio::spawn(async move {
+            starter.requeue_interrupted().await;
+            for worker_id in 0..num_workers {
+                tokio::spawn(starter.clone().run_worker(worker_id));
+            }
+            starter.run_purger(result_ttl_secs).await;
+        });
+
+        queue
+    }
+
+    pub fn db(&self) -> &DbConn {
+        &self.db_connection
+    }
+
+    pub fn workers(&self) -> Vec<WorkerState> {
+        self.workers.lock().expect("mutex was poisoned").clone()
+    }
+
+    /// IDs of the jobs being proven
+    pub fn active_tasks(&self) -> Vec<u32> {
+        self.workers()
+            .iter()
+            .filter_map(|worker| match worker {
+                WorkerState::Proving { task_id } => Some(*task_id),
+                WorkerState::Idle => None,
+            })
+            .collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ProofEvent> {
+        self.events.subscribe()
+    }
+
+    /// Latest stage of a job being proven
+    pub fn stage(&self, task_id: u32) -> Option<ProofStage> {
+        self.stages.lock().expect("mutex was poisoned").get(&task_id).cloned()
+    }
+
+    /// Latest stage of a job, whatever its state. Returns None if the job does not exist (e.g. it was cancelled)
+    pub async fn current_stage(&self, task_id: u32) -> Option<ProofStage> {
+        if let Some(stage) = self.stage(task_id) {
+            return Some(stage);
+        }
+
+        let job = proof_job::Entity::find_by_id(task_id)
+            .one(&self.db_connection)
+            .await.expect("failed to get proof job from DB")?;
+        let stage = match job.state {
+            JobState::Executing => ProofStage::Executing,
+            JobState::Queued => ProofStage::Queued,
+            // the worker just claimed the job
+            JobState::Proving => ProofStage::Proving { segment: 1, segments: job.segments.unwrap_or(1) as usize },
+            JobState::Ready => ProofStage::Ready,
+            JobState::Failed => ProofStage::Failed(job.error.unwrap_or_default()),
+        };
+        Some(stage)
+    }
+
+    fn emit(&self, task_id: u32, stage: ProofStage) {
+        {
+            let mut stages = self.stages.lock().expect("mutex was poisoned");
+            if stage.is_final() {
+                stages.remove(&task_id);
+            }
+            else {
+                stages.insert(task_id, stage.clone());
+            }
+        }
+        // sending only fails when nobody is listening
+        let _ = self.events.send(ProofEvent { task_id, stage });
+    }
+
+    fn set_worker_state(&self, worker_id: usize, state: WorkerState) {
+        self.workers.lock().expect("mutex was poisoned")[worker_id] = state;
+    }
+
+    /// Persists a new job, which is executing until its dry-run finishes. Returns the job ID
+    pub async fn push(&self, args: &GenProofArgs) -> u32 {
+        let job = proof_job::ActiveModel {
+            input: Set(to_string(args).unwrap()),
+            state: Set(JobState::Executing),
+            input_digest: Set(Some(args.input_digest())),
+            created_at: Set(now_secs()),
+            ..Default::default()
+        };
+        let result = proof_job::Entity::insert(job)
+            .exec(&self.db_connection)
+            .await.expect("failed to insert proof job in DB");
+        self.emit(result.last_insert_id, ProofStage::Executing);
+
+        result.last_insert_id
+    }
+
+    /// Queues a job given the result of its dry-run, and wakes a worker up.
+    /// Jobs whose dry-run failed are saved as failed right away, without their input.
+    pub async fn finish_dry_run(&self, task_id: u32, dry_run: Result<(u64, usize), String>) {
+        let mut job = proof_job::ActiveModel::default();
+        let stage = match dry_run {
+            Ok((cycles, segments)) => {
+                job.state = Set(JobState::Queued);
+                job.cycles = Set(Some(cycles as i64));
+                job.segments = Set(Some(segments as i64));
+                ProofStage::Queued
+            },
+            Err(error) => {
+                job.input = Set(String::new());
+                job.state = Set(JobState::Failed);
+                job.finished_at = Set(Some(now_secs()));
+                job.error = Set(Some(error.clone()));
+                ProofStage::Failed(error)
+            },
+        };
+        // the job may have been cancelled during its dry-run
+        let saved = proof_job::Entity::update_many()
+            .set(job)
+            .filter(
+                Condition::all()
+                    .add(proof_job::Column::Id.eq(task_id))
+                    .add(proof_job::Column::State.eq(JobState::Executing))
+            )
+            .exec(&self.db_connection)
+            .await.expect("failed to queue proof job in DB");
+        if saved.rows_affected == 1 {
+            self.emit(task_id, stage);
+            self.notify.notify_one();
+        }
+    }
+
+    /// Returns the latest job proving the same input, unless it failed
+    pub async fn find_cached(&self, input_digest: &str) -> Option<u32> {
+        proof_job::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(proof_job::Column::InputDigest.eq(input_digest))
+                    .add(proof_job::Column::State.ne(JobState::Failed))
+            )
+            .order_by_desc(proof_job::Column::Id)
+            .one(&self.db_connection)
+            .await.expect("failed to get cached proof job from DB")
+            .map(|job| job.id)
+    }
+
+    /// Proving speed of this machine (per worker), measured over the last proven jobs
+    pub async fn cycles_per_sec(&self) -> f64 {
+        let recent_jobs = proof_job::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(proof_job::Column::State.eq(JobState::Ready))
+                    .add(proof_job::Column::Cycles.is_not_null())
+            )
+            .order_by_desc(proof_job::Column::Id)
+            .limit(SPEED_SAMPLE_SIZE)
+            .all(&self.db_connection)
+            .await.expect("failed to get proven jobs from DB");
+
+        let (cycles, secs) = recent_jobs
+            .iter()
+            .filter_map(|job| Some((job.cycles?, job.finished_at? - job.started_at?)))
+            .fold((0, 0), |(total_cycles, total_secs), (cycles, secs)| (total_cycles + cycles, total_secs + secs));
+
+        if cycles > 0 && secs > 0 { cycles as f64 / secs as f64 } else { DEFAULT_CYCLES_PER_SEC }
+    }
+
+    /// Deletes a job, whatever its state. Returns false if the job does not exist.
+    /// The worker proving the job stops it, and moves on to the next one once it stopped.
+    /// A local proof only stops at its next checkpoint (e.g. once all segments are proven), which may take minutes
+    pub async fn cancel(&self, task_id: u32) -> bool {
+        let result = proof_job::Entity::delete_by_id(task_id)
+            .exec(&self.db_connection)
+            .await.expect("failed to delete proof job from DB");
+
+        if result.rows_affected == 1 {
+            if let Some(cancellation) = self.cancellations.lock().expect("mutex was poisoned").get(&task_id) {
+                cancellation.flag.store(true, Ordering::Relaxed);
+                // stores a permit if the worker is not waiting on the proof yet
+                cancellation.notify.notify_one();
+            }
+            self.emit(task_id, ProofStage::Cancelled);
+        }
+        result.rows_affected == 1
+    }
+
+    // periodically delete finished jobs, so receipts (which contain the holder's script) are not kept forever
+    async fn run_purger(&self, result_ttl_secs: i64) {
+        let mut interval = tokio::time::interval(Duration::from_secs(PURGE_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let purged = proof_job::Entity::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(proof_job::Column::State.is_in([JobState::Ready, JobState::Failed]))
+                        .add(proof_job::Column::FinishedAt.lt(now_secs() - result_ttl_secs))
+                )
+                .exec(&self.db_connection)
+                .await.expect("failed to purge proof jobs from DB");
+            if purged.rows_affected > 0 {
+                println!("Purged {} expired proof jobs", purged.rows_affected);
+            }
+        }
+    }
+
+    // jobs that were being proven when the server stopped are proven again.
+    // Jobs interrupted during their dry-run fail, as their cycles were never counted
+    async fn requeue_interrupted(&self) {
+        proof_job::Entity::update_many()
+            .col_expr(proof_job::Column::State, Expr::value(JobState::Queued))
+            .col_expr(proof_job::Column::StartedAt, Expr::value(Option::<i64>::None))
+            .filter(proof_job::Column::State.eq(JobState::Proving))
+            .exec(&self.db_connection)
+            .await.expect("failed to requeue interrupted proof jobs in DB");
+        proof_job::Entity::update_many()
+            .col_expr(proof_job::Column::State, Expr::value(JobState::Failed))
+            .col_expr(proof_job::Column::Input, Expr::value(String::new()))
+            .col_expr(proof_job::Column::FinishedAt, Expr::value(now_secs()))
+            .col_expr(proof_job::Column::Error, Expr::value("dry-run was interrupted by a restart"))
+            .filter(proof_job::Column::State.eq(JobState::Executing))
+            .exec(&self.db_connection)
+            .await.expect("failed to fail interrupted proof jobs in DB");
+    }
+
+    async fn run_worker(self, worker_id: usize) {
+        println!("Starting proving worker {}...", worker_id);
+
+        loop {
+            match self.claim_next().await {
+                Some(job) => {
+                    let task_id = job.id;
+                    self.set_worker_state(worker_id, WorkerState::Proving { task_id });
+                    let cancellation = Arc::new(Cancellation::default());
+                    self.cancellations.lock().expect("mutex was poisoned").insert(task_id, Arc::clone(&cancellation));
+                    // the job may have been cancelled before it could be signaled
+                    let exists = proof_job::Entity::find_by_id(task_id)
+                        .one(&self.db_connection)
+                        .await.expect("failed to get proof job from DB")
+                        .is_some();
+                    if exists {
+                        self.process(job, &cancellation).await;
+                    }
+                    self.cancellations.lock().expect("mutex was poisoned").remove(&task_id);
+                    self.set_worker_state(worker_id, WorkerState::Idle);
+                },
+                // No tasks remaining, wait for the next one
+                None => self.notify.notified().await,
+            }
+        }
+    }
+
+    // Marks the oldest queued job as being proven, and returns it
+    async fn claim_next(&self) -> Option<proof_job::Model> {
+        loop {
+            let job = proof_job::Entity::find()
+                .filter(proof_job::Column::State.eq(JobState::Queued))
+                .order_by_asc(proof_job::Column::Id)
+                .one(&self.db_connection)
+                .await.expect("failed to get queued proof job from DB")?;
+
+            // only claim the job if it is still queued
+            let claimed = proof_job::Entity::update_many()
+                .col_expr(proof_job::Column::State, Expr::value(JobState::Proving))
+                .col_expr(proof_job::Column::StartedAt, Expr::value(now_secs()))
+                .filter(
+                    Condition::all()
+                        .add(proof_job::Column::Id.eq(job.id))
+                        .add(proof_job::Column::State.eq(JobState::Queued))
+                )
+                .exec(&self.db_connection)
+                .await.expect("failed to claim proof job in DB");
+
+            if claimed.rows_affected == 1 {
+                return Some(job);
+            }
+        }
+    }
+
+    async fn process(&self, job: proof_job::Model, cancellation: &Cancellation) {
+        // a job whose input can't be read fails like any other, instead of taking the worker down
+        let result = match from_str::<GenProofArgs>(&job.input) {
+            Ok(args) => self.prove(&job, args, cancellation).await,
+            Err(err) => Err(format!("proof job input is not valid: {}", err)),
+        };
+
+        // save the proof (or the error) as the job result.
+        // The input holds the holder secret and credentials, it is only kept until the job is proven
+        let mut finished = proof_job::ActiveModel {
+            input: Set(String::new()),
+            finished_at: Set(Some(now_secs())),
+            ..Default::default()
+        };
+        let stage = match result {
+            Ok((receipt, prove_ms, verify_ms)) => {
+                finished.state = Set(JobState::Ready);
+                finished.receipt = Set(Some(bincode::serialize(&receipt).unwrap()));
+                finished.prove_ms = Set(Some(prove_ms as i64));
+                finished.verify_ms = Set(Some(verify_ms as i64));
+                ProofStage::Ready
+            },
+            Err(error) => {
+                println!("Proof job {} failed: {}", job.id, error);
+                finished.state = Set(JobState::Failed);
+                finished.error = Set(Some(error.clone()));
+                ProofStage::Failed(error)
+            },
+        };
+        // the job may have been cancelled while it was being proven
+        let saved = proof_job::Entity::update_many()
+            .set(finished)
+            .filter(
+                Condition::all()
+                    .add(proof_job::Column::Id.eq(job.id))
+                    .add(proof_job::Column::State.eq(JobState::Proving))
+            )
+            .exec(&self.db_connection)
+            .await.expect("failed to save proof job result in DB");
+        if saved.rows_affected == 0 {
+            println!("Proof job {} was cancelled, discarding its result", job.id);
+            self.stages.lock().expect("mutex was poisoned").remove(&job.id);
+        }
+        else {
+            self.emit(job.id, stage);
+        }
+    }
+
+    // Proves the job input, then verifies the receipt.
+    // Returns the receipt, and how long proving and verifying took, in milliseconds
+    async fn prove(&self, job: &proof_job::Model, args: GenProofArgs, cancellation: &Cancellation) -> Result<(Receipt, u64, u64), String> {
+        let input = args.zkvm_input();
+        let cycles_per_sec = self.cycles_per_sec().await;
+
+        // cycles and segments were counted by the dry-run, when the job was pushed
+        let progress = tokio::spawn(self.clone().report_progress(
+            job.id,
+            job.cycles.unwrap_or_default() as u64,
+            job.segments.unwrap_or(1) as usize,
+            cycles_per_sec,
+        ));
+        let mut proof = self.backend.prove(input, args.receipt_kind, Arc::clone(&cancellation.flag));
+        let proof = tokio::select! {
+            proof = &mut proof => proof,
+            () = cancellation.notify.notified() => {
+                // keep the worker busy until the proof really stops,
+                // so no more than PROVER_WORKERS proofs run at once
+                if !self.backend.stops_on_drop() {
+                    let _ = proof.await;
+                }
+                Err(prover::CANCELLED.to_string())
+            },
+        };
+        progress.abort();
+
+        let (receipt, prove_ms) = proof?;
+        self.emit(job.id, ProofStage::Verifying);
+        let verification = tokio::task::spawn_blocking(move || prover::verify(&receipt).map(|verify_ms| (receipt, verify_ms)))
+            .await
+            .unwrap_or_else(|err| Err(format!("verifier crashed: {}", err)));
+        verification.map(|(receipt, verify_ms)| (receipt, prove_ms, verify_ms))
+    }
+
+    // report the segment being proven, estimated from the measured proving speed
+    async fn report_progress(self, task_id: u32, cycles: u64, segments: usize, cycles_per_sec: f64) {
+        let start_time = Instant::now();
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        let mut last_segment = 0;
+        loop {
+            interval.tick().await;
+            let proven = start_time.elapsed().as_secs_f64() * cycles_per_sec / cycles.max(1) as f64;
+            let segment = ((proven * segments as f64) as usize + 1).min(segments);
+            if segment != last_segment {
+                self.emit(task_id, ProofStage::Proving { segment, segments });
+                last_segment = segment;
+            }
+        }
+    }
+}
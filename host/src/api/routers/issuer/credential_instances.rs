@@ -1,7 +1,16 @@
-use std::{sync::Arc, collections::HashSet};
-use entity::{credential, credential_instance};
-use shared::types::CredentialInstanceData;
-use sea_orm::{DbConn, EntityTrait, Set, QueryFilter, ColumnTrait, Condition, QuerySelect, FromQueryResult};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use entity::{credential, credential_instance, holder};
+use shared::{
+    types::CredentialInstanceData,
+    merkle::{Hash, MerkleTree, SortedMerkleTree, MerklePath, NonMembershipProof, encode_hash, decode_hash},
+};
+use sea_orm::{
+    DbConn, EntityTrait, Set, QueryFilter, QueryOrder, ColumnTrait, Condition, QuerySelect, FromQueryResult,
+    sea_query::Expr,
+};
 use axum::{
     routing::{Router, get, post},
     http::StatusCode, Json,
@@ -9,21 +18,53 @@ use axum::{
 };
 use sha2::{Sha256, Digest};
 use base64ct::{Base64, Encoding};
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 use serde_json::to_string;
+use tokio::join;
 
 use crate::adapters::RegistryContract;
 
 
 #[derive(Deserialize)]
 pub struct ModifyInstancesArgs {
+    // removed instances are kept as revoked, as holders could still prove them against older registry roots
     pub remove: Vec<u32>,
+    // revoked instances stay in the registry tree, but can no longer be proven unrevoked
+    #[serde(default)]
+    pub revoke: Vec<u32>,
     pub num_to_add: usize,
+    // UNIX timestamps (seconds) bounding when added instances can be proven. Valid from now, forever by default
+    #[serde(default)]
+    pub valid_from: Option<u64>,
+    #[serde(default)]
+    pub valid_until: Option<u64>,
 }
 
 #[derive(FromQueryResult)]
 struct InstanceHash {
+    id: u32,
     hash: String,
+    revoked: bool,
+}
+
+// Everything a holder needs to prove an instance is in the issuer's registry, and not revoked
+#[derive(Serialize)]
+pub struct InstanceWitness {
+    // base64 encoded root of the registry tree, as published by sync
+    root: String,
+    path: MerklePath,
+    // base64 encoded root of the revocation tree, as published by sync
+    revocation_root: String,
+    revocation: NonMembershipProof,
+}
+
+// Trees built over the credential instances in the issuer DB
+struct Registry {
+    // (instance ID, hash) of all instances, in leaf order
+    instances: Vec<(u32, Hash)>,
+    tree: MerkleTree,
+    // sorted tree of revoked instance hashes
+    revocation_tree: SortedMerkleTree,
 }
 
 #[derive(Clone)]
@@ -41,6 +82,7 @@ pub fn instances_router(db_connection: DbConn, registry: Arc<RegistryContract>)
             get(get_instances).post(modify_instances)
         )
         .route("/sync", post(sync_instances))
+        .route("/witness/:instance_id", get(get_witness))
         .with_state(state)
 }
 
@@ -62,28 +104,36 @@ pub async fn modify_instances(
     Path(credential_id): Path<u32>,
     Json(payload): Json<ModifyInstancesArgs>,
 ) -> (StatusCode, Json<bool>) {
-    // Remove credential instances
-    if !payload.remove.is_empty() {
-        credential_instance::Entity::delete_many()
+    // Revoke credential instances, removed ones included
+    if !payload.revoke.is_empty() || !payload.remove.is_empty() {
+        credential_instance::Entity::update_many()
+            .col_expr(credential_instance::Column::Revoked, Expr::value(true))
             .filter(
                 Condition::all()
-                    .add(credential_instance::Column::Id.is_in(payload.remove))
+                    .add(credential_instance::Column::Id.is_in(payload.revoke.into_iter().chain(payload.remove)))
                     .add(credential_instance::Column::CredentialId.eq(credential_id))
             )
             .exec(&state.db_connection)
-            .await.expect("failed to remove credentials from DB");
+            .await.expect("failed to revoke credentials in DB");
     }
     // Add credential instances
     if payload.num_to_add > 0 {
-        // get parent credential from DB
+        // get parent credential, and the holder it belongs to, from DB
         let maybe_credential = credential::Entity::find_by_id(credential_id)
+            .find_also_related(holder::Entity)
             .one(&state.db_connection)
             .await.expect("failed to get credential for given ID");
-        // check if the DB has such a credential
+        // check if the DB has such a credential, and its holder has registered a secret commitment
         match maybe_credential {
-            Some(credential) => {
+            Some((
+                credential @ credential::Model { removed: false, .. },
+                Some(holder::Model { commitment: Some(holder_commitment), removed: false, .. }),
+            )) => {
                 // Credential details are stringified JSON. Try parsing them as JSON Object
                 let mut new_instances: Vec<credential_instance::ActiveModel> = Vec::with_capacity(payload.num_to_add);
+                let valid_from = payload.valid_from.unwrap_or_else(|| {
+                    SystemTime::now().duration_since(UNIX_EPOCH).expect("system time is before the UNIX epoch").as_secs()
+                });
                 for _i in 0..payload.num_to_add {
                     let instance = CredentialInstanceData {
                         details: credential.details.clone(),
@@ -91,6 +141,10 @@ pub async fn modify_instances(
                         // shorter representation => faster parsing inside zkVM
                         nonce: Base64::encode_string(&rand::random::<u128>().to_ne_bytes()),
                         schema_id: credential.schema_id,
+                        // bind the instance to its holder, only they can prove with it
+                        holder_commitment: holder_commitment.clone(),
+                        valid_from,
+                        valid_until: payload.valid_until,
                     };
                     // obtain a stringified JSON representation of the credential instance
                     let data_str = to_string(&instance).unwrap();
@@ -98,6 +152,8 @@ pub async fn modify_instances(
                         credential_id: Set(credential_id),
                         data: Set(data_str.clone()),
                         hash: Set(Base64::encode_string(&Sha256::digest(data_str))),
+                        valid_from: Set(valid_from as i64),
+                        valid_until: Set(payload.valid_until.map(|valid_until| valid_until as i64)),
                         ..Default::default()
                     });
                 }
@@ -106,33 +162,108 @@ pub async fn modify_instances(
                 .exec(&state.db_connection)
                 .await.expect("failed to insert new credentials in DB");
             },
-            None => {},
+            // no such credential (or it was removed), or its holder can't prove with new instances
+            _ => return (StatusCode::UNPROCESSABLE_ENTITY, Json(false)),
         }
     }
 
     (StatusCode::ACCEPTED, Json(true))
 }
 
-// sync credential hashes on the registry contract to reflect the state of the issuer DB
-pub async fn sync_instances(State(state): State<AppState>) -> (StatusCode, Json<bool>) {
-    // get all credential hashes on the DB
-    let db_hashes: HashSet<String> = credential_instance::Entity::find()
+// Revoke all instances of removed credentials. Instances are never deleted, as they are in published roots
+pub(crate) async fn revoke_credential_instances(db_connection: &DbConn, credential_ids: Vec<u32>) {
+    credential_instance::Entity::update_many()
+        .col_expr(credential_instance::Column::Revoked, Expr::value(true))
+        .filter(credential_instance::Column::CredentialId.is_in(credential_ids))
+        .exec(db_connection)
+        .await.expect("failed to revoke credential instances in DB");
+}
+
+// Build the registry trees over the hashes of all credential instances (ordered by instance ID), and of revoked ones
+async fn load_registry(db_connection: &DbConn) -> Registry {
+    let rows: Vec<InstanceHash> = credential_instance::Entity::find()
         .select_only()
+        .column(credential_instance::Column::Id)
         .column(credential_instance::Column::Hash)
+        .column(credential_instance::Column::Revoked)
+        .order_by_asc(credential_instance::Column::Id)
         .into_model::<InstanceHash>()
-        .all(&state.db_connection)
+        .all(db_connection)
         .await
-        .unwrap()
-        .into_iter()
-        .map(|q_result| q_result.hash)
+        .unwrap();
+
+    let instances: Vec<(u32, Hash)> = rows
+        .iter()
+        .map(|row| (row.id, decode_hash(&row.hash).expect("credential instance hash in DB is not a valid hash")))
+        .collect();
+    let leaves: Vec<Hash> = instances.iter().map(|(_, hash)| *hash).collect();
+    let revoked: Vec<Hash> = instances
+        .iter()
+        .zip(&rows)
+        .filter(|(_, row)| row.revoked)
+        .map(|((_, hash), _)| *hash)
         .collect();
-    // get all credentials hashes on the registry contract
-    let registry_hashes: HashSet<String> = state.registry.get_credentials().await.into_iter().collect();
 
-    state.registry.modify_credentials(
-        registry_hashes.difference(&db_hashes).into_iter().cloned().collect(),
-        db_hashes.difference(&registry_hashes).into_iter().cloned().collect(),
-    ).await;
+    Registry {
+        instances,
+        tree: MerkleTree::new(&leaves),
+        revocation_tree: SortedMerkleTree::new(&revoked),
+    }
+}
+
+// get the proofs of inclusion of a credential instance in the registry tree, and of its absence from the revocation tree.
+// Fails with 409 when instances changed since the last sync, as the proofs would be against roots that were never published
+pub async fn get_witness(
+    State(state): State<AppState>,
+    Path(instance_id): Path<u32>,
+) -> (StatusCode, Json<Option<InstanceWitness>>) {
+    let registry = load_registry(&state.db_connection).await;
+    let (published_root, published_revocation_root) = join!(
+        state.registry.get_root(),
+        state.registry.get_revocation_root(),
+    );
+    if published_root != Some(encode_hash(&registry.tree.root()))
+        || published_revocation_root != Some(encode_hash(&registry.revocation_tree.root())) {
+        return (StatusCode::CONFLICT, Json(None));
+    }
+
+    let witness = registry.instances
+        .iter()
+        .position(|(id, _)| *id == instance_id)
+        .and_then(|index| {
+            // revoked instances have no proof of non-revocation
+            let revocation = registry.revocation_tree.non_membership(&registry.instances[index].1)?;
+            Some(InstanceWitness {
+                root: encode_hash(&registry.tree.root()),
+                path: registry.tree.path(index)?,
+                revocation_root: encode_hash(&registry.revocation_tree.root()),
+                revocation,
+            })
+        });
+
+    match witness {
+        Some(_) => (StatusCode::ACCEPTED, Json(witness)),
+        None => (StatusCode::NOT_FOUND, Json(witness)),
+    }
+}
+
+// publish the roots of the credential hashes (all and revoked) in the issuer DB on the registry contract
+pub async fn sync_instances(State(state): State<AppState>) -> (StatusCode, Json<bool>) {
+    let registry = load_registry(&state.db_connection).await;
+    let root = encode_hash(&registry.tree.root());
+    let revocation_root = encode_hash(&registry.revocation_tree.root());
+
+    // only start new epochs if credential instances changed since the last ones
+    let (current_root, current_revocation_root) = join!(
+        state.registry.get_root(),
+        state.registry.get_revocation_root(),
+    );
+    if current_root.as_ref() != Some(&root) {
+        state.registry.publish_root(root).await;
+    }
+    if current_revocation_root.as_ref() != Some(&revocation_root) {
+        state.registry.publish_revocation_root(revocation_root).await;
+    }
 
     (StatusCode::ACCEPTED, Json(true))
 }
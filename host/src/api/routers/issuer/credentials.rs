@@ -1,5 +1,5 @@
 use entity::credential;
-use sea_orm::{DbConn, EntityTrait, Set, QueryFilter, ColumnTrait};
+use sea_orm::{DbConn, EntityTrait, Set, QueryFilter, ColumnTrait, Condition, sea_query::Expr};
 use axum::{
     routing::{Router, post, get},
     http::StatusCode, Json,
@@ -8,11 +8,13 @@ use axum::{
 use serde::Deserialize;
 use shared::types::SchemaId;
 
+use super::credential_instances::revoke_credential_instances;
+
 
 #[derive(Deserialize)]
 pub struct ModifyCredentialsArgs {
     pub holder_id: u32,
-    // vector of credential IDs to be removed for this user
+    // vector of credential IDs to be removed for this user. Their instances are revoked, not deleted
     pub remove: Vec<u32>,
     // vector of credential details (JSON strings) to be added for this user
     pub add: Vec<(SchemaId, String)>,
@@ -38,7 +40,11 @@ pub async fn get_credentials(
     Path(holder_id): Path<u32>,
 ) -> (StatusCode, Json<Vec<credential::Model>>) {
     let credentials = credential::Entity::find()
-        .filter(credential::Column::HolderId.eq(holder_id))
+        .filter(
+            Condition::all()
+                .add(credential::Column::HolderId.eq(holder_id))
+                .add(credential::Column::Removed.eq(false))
+        )
         .all(&state.db_connection)
         .await.expect("failed to get holder credentials from DB");
     
@@ -65,9 +71,11 @@ pub async fn modify_credentials(
         .flatten()
         .collect();
 
-    // Remove credentials
+    // Remove credentials, revoking their instances first
     if !to_remove.is_empty() {
-        credential::Entity::delete_many()
+        revoke_credential_instances(&state.db_connection, to_remove.clone()).await;
+        credential::Entity::update_many()
+            .col_expr(credential::Column::Removed, Expr::value(true))
             .filter(credential::Column::Id.is_in(to_remove))
             .exec(&state.db_connection)
             .await.expect("failed to remove credentials from DB");
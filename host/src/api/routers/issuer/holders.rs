@@ -1,23 +1,40 @@
-use entity::holder;
-use sea_orm::{DbConn, EntityTrait, Set, QueryFilter, ColumnTrait};
+use entity::{credential, holder};
+use sea_orm::{DbConn, EntityTrait, Set, QueryFilter, QuerySelect, ColumnTrait, sea_query::Expr};
 use axum::{
     routing::{Router, get},
     http::StatusCode, Json,
     extract::State,
 };
 use serde::Deserialize;
+use base64ct::{Base64, Encoding};
+
+use super::credential_instances::revoke_credential_instances;
 
 
 #[derive(Deserialize)]
 pub struct ModifyHoldersArgs {
+    // removed holders are kept, with their credentials removed and instances revoked
     pub remove: Vec<u32>,
     pub add: Vec<HolderInfo>,
+    // set the commitment of existing holders, e.g. registered before commitments existed.
+    // Instances issued before embed the previous commitment, so only new instances use it
+    #[serde(default)]
+    pub set_commitment: Vec<HolderCommitment>,
 }
 
 #[derive(Deserialize)]
 pub struct HolderInfo {
     pub first_name: String,
     pub last_name: String,
+    // base64 encoded sha256 hash of the holder's secret, embedded in their credential instances
+    pub commitment: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct HolderCommitment {
+    pub id: u32,
+    // base64 encoded sha256 hash of the holder's secret
+    pub commitment: String,
 }
 
 #[derive(Clone)]
@@ -40,7 +57,9 @@ pub fn holders_router(db_connection: DbConn) -> Router {
 pub async fn get_holders(
     State(state): State<AppState>,
 ) -> (StatusCode, Json<Vec<holder::Model>>) {
-    let holders = holder::Entity::find().all(&state.db_connection).await.expect("failed to get post from DB");
+    let holders = holder::Entity::find()
+        .filter(holder::Column::Removed.eq(false))
+        .all(&state.db_connection).await.expect("failed to get post from DB");
     
     (StatusCode::ACCEPTED, Json(holders))
 }
@@ -49,18 +68,46 @@ pub async fn modify_holders(
     State(state): State<AppState>,
     Json(payload): Json<ModifyHoldersArgs>,
 ) -> (StatusCode, Json<bool>) {
+    // commitments must be sha256 hashes, or no holder secret could ever match them
+    let commitments = payload.add
+        .iter()
+        .filter_map(|holder_info| holder_info.commitment.as_ref())
+        .chain(payload.set_commitment.iter().map(|holder_commitment| &holder_commitment.commitment));
+    for commitment in commitments {
+        if !Base64::decode_vec(commitment).is_ok_and(|hash| hash.len() == 32) {
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(false));
+        }
+    }
+
     let new_holders: Vec<holder::ActiveModel> = payload.add
         .iter()
         .map(|holder_info| holder::ActiveModel {
             first_name: Set(holder_info.first_name.clone()),
             last_name: Set(holder_info.last_name.clone()),
+            commitment: Set(holder_info.commitment.clone()),
             ..Default::default()
         })
         .collect();
 
-    // Remove holders
+    // Remove holders, along with their credentials, revoking their instances first
     if !payload.remove.is_empty() {
-        holder::Entity::delete_many()
+        let credential_ids: Vec<u32> = credential::Entity::find()
+            .select_only()
+            .column(credential::Column::Id)
+            .filter(credential::Column::HolderId.is_in(payload.remove.clone()))
+            .into_tuple()
+            .all(&state.db_connection)
+            .await.expect("failed to get holder credentials from DB");
+        if !credential_ids.is_empty() {
+            revoke_credential_instances(&state.db_connection, credential_ids.clone()).await;
+            credential::Entity::update_many()
+                .col_expr(credential::Column::Removed, Expr::value(true))
+                .filter(credential::Column::Id.is_in(credential_ids))
+                .exec(&state.db_connection)
+                .await.expect("failed to remove holder credentials from DB");
+        }
+        holder::Entity::update_many()
+            .col_expr(holder::Column::Removed, Expr::value(true))
             .filter(holder::Column::Id.is_in(payload.remove))
             .exec(&state.db_connection)
             .await.expect("failed to remove holders from DB");
@@ -71,6 +118,14 @@ pub async fn modify_holders(
             .exec(&state.db_connection)
             .await.expect("failed to insert new holders in DB");
     }
+    // Update commitments
+    for holder_commitment in payload.set_commitment {
+        holder::Entity::update_many()
+            .col_expr(holder::Column::Commitment, Expr::value(holder_commitment.commitment))
+            .filter(holder::Column::Id.eq(holder_commitment.id))
+            .exec(&state.db_connection)
+            .await.expect("failed to update holder commitment in DB");
+    }
 
     (StatusCode::ACCEPTED, Json(true))
 }
@@ -1,29 +1,42 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    collections::{BTreeMap, HashMap},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 use methods::{ZK_PROVER_ELF, ZK_PROVER_ID};
-use shared::types::ScriptLang;
-use std::time::Instant;
-use sea_orm::DbConn;
+use shared::types::{ScriptLang, ScriptValue, Challenge, JOURNAL_VERSION};
+use entity::nullifier;
+use sea_orm::{DbConn, EntityTrait, QueryFilter, ColumnTrait, Condition, Set, SqlErr};
 use axum::{
     extract::State, http::StatusCode, routing::{get, post, Router}, Json
 };
-use risc0_zkvm::{
-    Receipt,
-    serde::from_slice,
-};
+use risc0_zkvm::{Receipt, InnerReceipt};
 use serde::{Serialize, Deserialize};
-use shared::types::ZkCommit;
+use shared::{types::ZkCommit, schema::schema_hash};
 use base64ct::{Base64, Encoding};
+use sha2::{Sha256, Digest};
 use tokio::join;
 
 use crate::adapters::RegistryContract;
+use crate::config;
+use crate::journal::{journal_version, decode_journal};
 
 
 #[derive(Deserialize, Clone)]
 pub struct CheckArgs {
-    // list of credential issuers, in the same order of cred_hashes from the ZKP journal
+    // list of credential issuers, in the same order of cred_roots from the ZKP journal
     cred_issuers: Vec<String>,
-    // Base64 ecncoded risc0 Receipt
+    // Base64 ecncoded risc0 Receipt, either composite or succinct
     base64_receipt: String,
+    // script the proof was generated for, required when the journal only commits its hash
+    #[serde(default)]
+    script: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ChallengeArgs {
+    // ask the holder for a nullifier under this scope, to detect repeated presentations
+    scope: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -49,11 +62,21 @@ pub struct ModifyRequestsArgs {
 #[derive(Serialize, Clone)]
 pub struct Request {
     pub status: RequestStatus,
-    pub cred_hashes: Vec<String>,
+    pub cred_roots: Vec<String>,
     pub cred_schemas: Vec<String>,
     pub lang: ScriptLang,
     pub script: String,
-    pub result: bool,
+    pub nullifier: Option<String>,
+    // values derived from the credentials, and disclosed by the script
+    pub output: ScriptValue,
+    // per credential, fields disclosed verbatim by the holder (JSON pointer => JSON encoded value)
+    pub disclosed: Vec<BTreeMap<String, String>>,
+}
+
+#[derive(Clone)]
+pub struct IssuedChallenge {
+    pub challenge: Challenge,
+    pub consumed: bool,
 }
 
 #[derive(Clone)]
@@ -61,29 +84,167 @@ pub struct AppState {
     db_connection: DbConn,
     registry: Arc<RegistryContract>,
     requests: Arc<Mutex<Vec<Request>>>,
+    verifier_id: String,
+    // map nonce => issued challenge
+    challenges: Arc<Mutex<HashMap<String, IssuedChallenge>>>,
+    // accept fake receipts, for testing only
+    dev_mode: bool,
 }
 
+// how long a holder has to present a proof after requesting a challenge
+const CHALLENGE_TTL_SECS: u64 = 10 * 60;
+// how long after an issuer publishes a new revocation root we still accept proofs against the previous ones
+const REVOCATION_ROOT_MAX_AGE_SECS: u64 = 60 * 60;
+
 
 pub fn verifier_router(db_connection: DbConn, registry: Arc<RegistryContract>) -> Router {
     let app_state = AppState {
         db_connection,
         registry,
         requests: Arc::new(Mutex::new(Vec::new())),
+        verifier_id: std::env::var("VERIFIER_ID").expect("VERIFIER_ID must be set."),
+        challenges: Arc::new(Mutex::new(HashMap::new())),
+        dev_mode: config::dev_mode(),
     };
 
     
     Router::new()
         //.route("/check-script", post(gen_js_proof))
+        .route("/challenge", post(issue_challenge))
         .route("/check", post(check_presentation))
         .route("/presentations", get(get_presentations).post(modify_presentations))
         .with_state(app_state)
 }
 
 
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("system time is before the UNIX epoch").as_secs()
+}
+
+// issue a fresh challenge, which the holder must bind their next proof to
+pub async fn issue_challenge(
+    State(state): State<AppState>,
+    Json(payload): Json<ChallengeArgs>,
+) -> (StatusCode, Json<Challenge>) {
+    let now = now_secs();
+    let challenge = Challenge {
+        nonce: Base64::encode_string(&rand::random::<u128>().to_ne_bytes()),
+        verifier_id: state.verifier_id.clone(),
+        issued_at: now,
+        expires_at: now + CHALLENGE_TTL_SECS,
+        scope: payload.scope,
+    };
+
+    {
+        let mut challenges = state.challenges.lock().expect("mutex was poisoned");
+        // forget expired challenges, they would be rejected anyway
+        challenges.retain(|_, issued| issued.challenge.expires_at > now);
+        challenges.insert(challenge.nonce.clone(), IssuedChallenge { challenge: challenge.clone(), consumed: false });
+    }
+
+    (StatusCode::ACCEPTED, Json(challenge))
+}
+
+// Make sure a challenge committed in a ZKP journal was issued by us, is not expired and was never used before.
+// As the challenge must match the issued one, the time credentials were proven valid at is at most CHALLENGE_TTL_SECS old.
+// On success, the challenge is consumed so the same receipt cannot be presented again.
+fn consume_challenge(state: &AppState, challenge: &Challenge) -> Result<(), String> {
+    let mut challenges = state.challenges.lock().expect("mutex was poisoned");
+    match challenges.get_mut(&challenge.nonce) {
+        None => Err("Unknown challenge".to_string()),
+        Some(issued) => {
+            if issued.challenge != *challenge {
+                Err("Challenge does not match the one issued by this verifier".to_string())
+            }
+            else if issued.challenge.expires_at <= now_secs() {
+                Err("Challenge has expired".to_string())
+            }
+            else if issued.consumed {
+                Err("Challenge was already used".to_string())
+            }
+            else {
+                issued.consumed = true;
+                Ok(())
+            }
+        },
+    }
+}
+
+// Get the (scope, nullifier) pair of a presentation made for a scoped challenge
+fn scoped_nullifier(journal: &ZkCommit) -> Result<Option<(&String, &String)>, String> {
+    match (&journal.challenge.scope, &journal.nullifier) {
+        (None, _) => Ok(None),
+        // the guest does not compute nullifiers for failed executions
+        (Some(_), None) if journal.has_error => Ok(None),
+        (Some(_), None) => Err("Proof is missing the nullifier requested by the challenge".to_string()),
+        (Some(scope), Some(nullifier)) => Ok(Some((scope, nullifier))),
+    }
+}
+
+// Check that the holder did not already present under the scope of the challenge, without recording it
+async fn check_nullifier(state: &AppState, journal: &ZkCommit) -> Result<(), String> {
+    match scoped_nullifier(journal)? {
+        Some((scope, nullifier)) => {
+            let seen = nullifier::Entity::find()
+                .filter(
+                    Condition::all()
+                        .add(nullifier::Column::Scope.eq(scope))
+                        .add(nullifier::Column::Nullifier.eq(nullifier))
+                )
+                .one(&state.db_connection)
+                .await.expect("failed to get nullifier from DB");
+            match seen {
+                Some(_) => Err("Holder already presented for this scope".to_string()),
+                None => Ok(()),
+            }
+        },
+        None => Ok(()),
+    }
+}
+
+// Remember the nullifier of an accepted presentation made for a scoped challenge, across restarts.
+// Fails if the same holder already presented under that scope.
+async fn record_nullifier(state: &AppState, journal: &ZkCommit) -> Result<(), String> {
+    match scoped_nullifier(journal)? {
+        Some((scope, nullifier)) => {
+            let seen = nullifier::ActiveModel {
+                scope: Set(scope.clone()),
+                nullifier: Set(nullifier.clone()),
+                created_at: Set(now_secs() as i64),
+                ..Default::default()
+            };
+            // the (scope, nullifier) pair is unique in the DB
+            match nullifier::Entity::insert(seen).exec(&state.db_connection).await {
+                Ok(_) => Ok(()),
+                Err(err) if matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                    Err("Holder already presented for this scope".to_string())
+                },
+                Err(err) => panic!("failed to insert nullifier in DB: {}", err),
+            }
+        },
+        None => Ok(()),
+    }
+}
+
+// Get the script a ZKP journal was generated for, either committed in the journal or provided by the holder.
+// A provided script must match the hash committed in the journal
+fn resolve_script(journal: &ZkCommit, provided: &Option<String>) -> Result<String, String> {
+    let script = match (&journal.script, provided) {
+        (Some(script), _) | (None, Some(script)) => script,
+        (None, None) => return Err("Script must be provided, as the proof only commits its hash".to_string()),
+    };
+    if Base64::encode_string(&Sha256::digest(script.as_bytes())) != journal.script_hash {
+        return Err("Script does not match the hash committed in the proof".to_string());
+    }
+
+    Ok(script.clone())
+}
+
 // Check a verifiable credential presentation submitted by a user
 // 1) Check the ZKP, and parse the journal (we do this first as it takes the least amount of time)
-// 2) Check that provided credentials are in the issuer's registry contract
-// 3) fetch the schema, make sure the schema matches stuff in the script
+// 2) Check that the proof is bound to a fresh challenge issued by us, and the holder did not present under its scope before
+// 3) Check that provided credentials are in the issuer's registry contract, and not revoked
+// 4) fetch the schemas, and make sure the credentials were validated against them
 pub async fn check_presentation(
     State(state): State<AppState>,
     Json(payload): Json<CheckArgs>
@@ -92,14 +253,47 @@ pub async fn check_presentation(
     
     // Measure ZKP verification time
     let start_time = Instant::now();
-    // Verify ZKP
-    let (verdict, error, journal) = match receipt.verify(ZK_PROVER_ID) {
-        Ok(()) => {
+    // Only journals of the current version can be verified, older ones were proven by older guests (with other image IDs)
+    let verification = match journal_version(&receipt.journal.bytes) {
+        Ok(JOURNAL_VERSION) => Ok(()),
+        Ok(version) => Err(format!("Unsupported journal version {}, only version {} is accepted", version, JOURNAL_VERSION)),
+        Err(err) => Err(err),
+    };
+    // Verify ZKP, composite and succinct receipts are both accepted
+    let verification = verification.and_then(|()| match receipt.inner {
+        InnerReceipt::Flat(_) | InnerReceipt::Succinct(_) => receipt.verify(ZK_PROVER_ID).map_err(|err| err.to_string()),
+        // fake receipts come from holders in dev mode
+        InnerReceipt::Fake if state.dev_mode => receipt.verify(ZK_PROVER_ID).map_err(|err| err.to_string()),
+        InnerReceipt::Fake => Err("Fake receipts are only accepted in dev mode".to_string()),
+        _ => Err("Unsupported receipt kind".to_string()),
+    });
+    // parse the ZKP journal
+    let verification = verification.and_then(|()| decode_journal(&receipt.journal.bytes));
+    let (verdict, error, journal) = match verification {
+        Ok(journal) => {
             println!("ZKP verification time: {:?}", start_time.elapsed());
-            // parse the ZKP journal
-            let journal: ZkCommit = from_slice(&receipt.journal.bytes).unwrap();
+            // check that we know the script the proof was generated for
+            let script_res = resolve_script(&journal, &payload.script);
+            // the guest commits failed checks (e.g. a wrong holder secret, expired or revoked credentials) as errors
+            if journal.has_error {
+                (false, Option::Some(journal.err_msg.clone()), Option::Some(journal.clone()))
+            }
+            else if let Err(script_error) = &script_res {
+                (false, Option::Some(script_error.clone()), Option::None)
+            }
+            // check that the proof was generated for a challenge we issued (and only once)
+            else if let Err(challenge_error) = consume_challenge(&state, &journal.challenge) {
+                (false, Option::Some(challenge_error), Option::None)
+            }
+            // check that the holder did not present for the challenge's scope before
+            else if let Err(nullifier_error) = check_nullifier(&state, &journal).await {
+                (false, Option::Some(nullifier_error), Option::None)
+            }
             // check that all vectors containing credential information have the same length
-            if journal.cred_hashes.len() != journal.cred_schemas.len() || journal.cred_hashes.len() != payload.cred_issuers.len() {
+            else if journal.cred_roots.len() != journal.cred_schemas.len()
+                || journal.cred_roots.len() != journal.cred_schema_hashes.len()
+                || journal.cred_roots.len() != journal.revocation_roots.len()
+                || journal.cred_roots.len() != payload.cred_issuers.len() {
                 (
                     false,
                     Option::Some("Vectors containing credential information must be of the same length".to_string()),
@@ -107,16 +301,24 @@ pub async fn check_presentation(
                 )
             }
             else {
-                // Concurrently request 2 things from the regitry contract (to lower latency)
-                // 1- make sure all presented credential hashes are in the registry contract
-                // 2- fetch all credential schemas
-                let (registry_checks, schemas) = join!(
-                    state.registry.check_credentials(journal.cred_hashes
+                // Concurrently request 3 things from the regitry contract (to lower latency)
+                // 1- make sure all presented credentials were proven against a registry root of their issuer
+                // 2- make sure all presented credentials were proven unrevoked against a recent revocation root of their issuer
+                // 3- fetch all credential schemas
+                let (registry_checks, revocation_checks, schemas) = join!(
+                    state.registry.check_roots(journal.cred_roots
                         .iter()
                         .enumerate()
-                        .map(|(i, cred_hash)| (payload.cred_issuers[i].clone(), cred_hash.clone()))
+                        .map(|(i, cred_root)| (payload.cred_issuers[i].clone(), cred_root.clone()))
                         .collect()
                     ),
+                    state.registry.check_revocation_roots(journal.revocation_roots
+                        .iter()
+                        .enumerate()
+                        .map(|(i, revocation_root)| (payload.cred_issuers[i].clone(), revocation_root.clone()))
+                        .collect(),
+                        REVOCATION_ROOT_MAX_AGE_SECS,
+                    ),
                     state.registry.get_schemas(journal.cred_schemas
                         .iter()
                         .enumerate()
@@ -129,7 +331,15 @@ pub async fn check_presentation(
                 if registry_checks.contains(&false) {
                     (
                         false,
-                        Option::Some("Some provided credential is not valid for the given issuer on the registry contract".to_string()),
+                        Option::Some("Some provided credential is not in a registry root of the given issuer on the registry contract".to_string()),
+                        Option::None
+                    )
+                }
+                // revocation error
+                else if revocation_checks.contains(&false) {
+                    (
+                        false,
+                        Option::Some("Some provided credential was not proven unrevoked against a recent revocation root of the given issuer".to_string()),
                         Option::None
                     )
                 }
@@ -141,18 +351,36 @@ pub async fn check_presentation(
                         Option::None
                     )
                 }
+                // make sure the credentials were validated against the schemas registered by their issuers
+                else if schemas
+                    .iter()
+                    .zip(&journal.cred_schema_hashes)
+                    .any(|(schema, committed_hash)| schema_hash(schema) != *committed_hash) {
+                    (
+                        false,
+                        Option::Some("Some provided credential was not validated against the schema registered by its issuer".to_string()),
+                        Option::None
+                    )
+                }
+                // only remember the nullifier once the presentation is accepted
+                // (checked again, as another presentation under the same scope may have been accepted meanwhile)
+                else if let Err(nullifier_error) = record_nullifier(&state, &journal).await {
+                    (false, Option::Some(nullifier_error), Option::None)
+                }
                 else {
-                    // if ZK-Program did not have errors, add to pending requests
-                    if !(&journal.has_error) {
-                        let journal_clone = journal.clone();
+                    // add to pending requests
+                    let journal_clone = journal.clone();
+                    if let Some(output) = journal_clone.output {
                         let mut requests = state.requests.lock().expect("mutex was poisoned");
                         requests.push(Request {
                             status: RequestStatus::Pending,
-                            cred_hashes: journal_clone.cred_hashes,
+                            cred_roots: journal_clone.cred_roots,
                             cred_schemas: schemas,
                             lang: journal_clone.lang,
-                            script: journal_clone.script,
-                            result: journal_clone.result,
+                            script: script_res.unwrap(),
+                            nullifier: journal_clone.nullifier,
+                            output,
+                            disclosed: journal_clone.disclosed,
                         });
                     }
 
@@ -161,7 +389,7 @@ pub async fn check_presentation(
             }
         },
         Err(error) => {
-            (false, Option::Some(error.to_string()), Option::None)
+            (false, Option::Some(error), Option::None)
         },
     };
 
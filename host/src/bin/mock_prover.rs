@@ -0,0 +1,62 @@
+// Local stand-in for a remote proving service, to run the API with PROVER_BACKEND="remote" without one.
+// It proves with the local prover, so set RISC0_DEV_MODE=1 to get fake receipts instantly.
+// Run with `cargo run --bin mock_prover`, and set REMOTE_PROVER_URL="http://127.0.0.1:4000"
+
+use methods::ZK_PROVER_ELF;
+use shared::types::{ReceiptKind, RemoteProofRequest, RemoteProofResponse};
+use risc0_zkvm::{ExecutorEnv, default_prover};
+use axum::{routing::{Router, post}, Json, http::StatusCode};
+use std::{
+    net::{SocketAddr, IpAddr, Ipv4Addr},
+    time::Instant,
+};
+use base64ct::{Base64, Encoding};
+
+
+#[tokio::main]
+async fn main() {
+    let app = Router::new().route("/prove", post(prove_handler));
+
+    let addr = SocketAddr::from((
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        4000
+    ));
+    println!("mock prover listening on {}", addr);
+
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
+
+async fn prove_handler(Json(payload): Json<RemoteProofRequest>) -> (StatusCode, Json<RemoteProofResponse>) {
+    let result = tokio::task::spawn_blocking(move || prove(payload))
+        .await
+        .unwrap_or_else(|err| Err(format!("prover crashed: {}", err)));
+
+    let response = match result {
+        Ok((base64_receipt, prove_ms)) => RemoteProofResponse { base64_receipt: Some(base64_receipt), error: None, prove_ms },
+        Err(error) => RemoteProofResponse { base64_receipt: None, error: Some(error), prove_ms: 0 },
+    };
+    (StatusCode::OK, Json(response))
+}
+
+fn prove(request: RemoteProofRequest) -> Result<(String, u64), String> {
+    if request.receipt_kind != ReceiptKind::Composite {
+        return Err("the mock prover only produces composite receipts".to_string());
+    }
+
+    let env = ExecutorEnv::builder()
+        .write(&request.input)
+        .map_err(|err| err.to_string())?
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let start_time = Instant::now();
+    let receipt = default_prover()
+        .prove_elf(env, ZK_PROVER_ELF)
+        .map_err(|err| err.to_string())?;
+    let prove_ms = start_time.elapsed().as_millis() as u64;
+
+    Ok((Base64::encode_string(&bincode::serialize(&receipt).unwrap()), prove_ms))
+}
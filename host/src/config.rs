@@ -0,0 +1,19 @@
+// Dev mode makes the holder produce fake receipts (risc0 dev mode), and the verifier accept them.
+// It must never be enabled in production, as fake receipts prove nothing.
+pub fn dev_mode() -> bool {
+    matches!(std::env::var("DEV_MODE").as_deref(), Ok("true") | Ok("1"))
+}
+
+// Sets up risc0 dev mode according to DEV_MODE, so it can only be enabled explicitly
+pub fn init_dev_mode() {
+    if dev_mode() {
+        std::env::set_var("RISC0_DEV_MODE", "1");
+        println!("==================================================================");
+        println!("WARNING: DEV_MODE is enabled, proofs are FAKE and prove nothing.");
+        println!("The verifier accepts fake receipts. NEVER use this in production.");
+        println!("==================================================================");
+    }
+    else {
+        std::env::remove_var("RISC0_DEV_MODE");
+    }
+}
@@ -0,0 +1,22 @@
+use risc0_zkvm::serde::from_slice;
+use shared::types::{VersionedJournal, ZkCommit};
+
+
+/// Reads the version of a journal committed by the guest, without decoding the commit
+pub fn journal_version(bytes: &[u8]) -> Result<u32, String> {
+    // the version is the first word of the (version, commit) tuple
+    bytes
+        .get(..4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .ok_or("Invalid journal: it has no version".to_string())
+}
+
+/// Decodes a journal committed by the guest, in whichever version of the format it was committed
+pub fn decode_versioned_journal(bytes: &[u8]) -> Result<VersionedJournal, String> {
+    from_slice(bytes).map_err(|err| format!("Invalid journal: {}", err))
+}
+
+/// Decodes a journal committed by the guest. Only journals of the current version can be decoded
+pub fn decode_journal(bytes: &[u8]) -> Result<ZkCommit, String> {
+    decode_versioned_journal(bytes).and_then(VersionedJournal::into_current)
+}
@@ -1,6 +1,8 @@
 mod api;
 mod db;
 mod adapters;
+mod config;
+mod journal;
 
 use api::api_start;
 use db::db_start;
@@ -14,6 +16,8 @@ async fn main() {
     dotenv().ok();
     // Initialize tracing. In order to view logs, run `RUST_LOG=info cargo run`
     env_logger::init();
+    // must run before any proof is generated or verified
+    config::init_dev_mode();
 
     // adapter to interact with the registry contract
     let registry = RegistryContract::new();
@@ -5,10 +5,16 @@ use risc0_zkvm::{
     guest::env,
     sha::{self, Sha256},
 };
-use shared::types::{ZkCommit, ZkvmInput, CredentialInstanceData, ScriptLang};
-use rhai::{Engine, Scope, Dynamic};
-//use boa_engine::{Context, Source};
-use serde_json::de::from_str;
+use shared::{
+    types::{ZkCommit, ZkvmInput, CredentialInstanceData, ScriptLang, ScriptLimits, ScriptValue, Challenge, VersionedJournal},
+    merkle::{MerklePath, NonMembershipProof, encode_hash},
+    schema::{self, schema_hash},
+    rhai_std,
+};
+use boa_engine::{Context, Source, JsValue, property::Attribute, context::HostHooks};
+use chrono::NaiveDateTime;
+use serde_json::{de::from_str, to_string, Value};
+use std::collections::BTreeMap;
 use base64ct::{Base64, Encoding};
 
 risc0_zkvm::guest::entry!(main);
@@ -22,6 +28,21 @@ pub fn main() {
     let script_lang: ScriptLang = inputs.lang;
     // get script
     let input_script: String = inputs.script;
+    let script_hash = Base64::encode_string(sha::Impl::hash_bytes(input_script.as_bytes()).as_bytes());
+    // the script is committed as is, unless the holder asked to only commit its hash
+    let committed_script = if inputs.script_hash_only { None } else { Some(input_script.clone()) };
+    // get script engine limits (committed, so verifiers know what the script was allowed to do)
+    let limits: ScriptLimits = inputs.limits;
+    // get verifier challenge (echoed in the journal as is)
+    let challenge: Challenge = inputs.challenge;
+
+    // get inclusion proofs of the credentials in their issuers' registries
+    let cred_paths: Vec<MerklePath> = inputs.cred_paths;
+    // get proofs that the credentials are not revoked by their issuers
+    let cred_revocations: Vec<NonMembershipProof> = inputs.cred_revocations;
+    // get the schemas of the credentials, and their hashes to be committed
+    let cred_schema_docs: Vec<String> = inputs.cred_schema_docs;
+    let cred_schema_hashes: Vec<String> = cred_schema_docs.iter().map(|schema| schema_hash(schema)).collect();
 
     // validate that credentials are JSON objects with correct structure
     let credentials_res: Result<Vec<CredentialInstanceData>, _> = credentials_str
@@ -29,73 +50,255 @@ pub fn main() {
         .map(|cred_str| from_str(&cred_str))
         .collect();
 
+    // calculate sha256 hash of each credential
+    // the hashes are never committed, so presentations can't be linked through them
+    let cred_hashes: Vec<[u8; 32]> = credentials_str
+        .iter()
+        .map(|cred| sha::Impl::hash_bytes(cred.as_bytes()).as_bytes().try_into().unwrap())
+        .collect();
+
+    // calculate the registry root each credential is included in
+    let cred_roots_res: Option<Vec<String>> = if cred_paths.len() == cred_hashes.len() {
+        cred_hashes
+            .iter()
+            .zip(&cred_paths)
+            .map(|(cred_hash, path)| path.root(cred_hash).map(|root| encode_hash(&root)))
+            .collect()
+    }
+    else {
+        None
+    };
+
+    // calculate the revocation root each credential is proven absent from
+    let revocation_roots_res: Option<Vec<String>> = if cred_revocations.len() == cred_hashes.len() {
+        cred_hashes
+            .iter()
+            .zip(&cred_revocations)
+            .map(|(cred_hash, proof)| proof.root(cred_hash).map(|root| encode_hash(&root)))
+            .collect()
+    }
+    else {
+        None
+    };
+
     // stop if we found any errors
-    if credentials_res.is_err() {
-        env::commit(&ZkCommit {
+    if credentials_res.is_err() || cred_roots_res.is_none() || revocation_roots_res.is_none() {
+        env::commit(&VersionedJournal::from(ZkCommit {
             has_error: true,
-            err_msg: "failed to parse credentials".to_string(),
-            cred_hashes: Vec::new(),
+            err_msg: if credentials_res.is_err() {
+                "failed to parse credentials".to_string()
+            } else if cred_roots_res.is_none() {
+                "invalid credential registry paths".to_string()
+            } else {
+                "some credential is revoked, or its revocation proof is invalid".to_string()
+            },
+            cred_roots: Vec::new(),
+            revocation_roots: Vec::new(),
             cred_schemas: Vec::new(),
+            cred_schema_hashes: Vec::new(),
             lang: inputs.lang,
-            script: input_script,
-            result: false,
-        });
-        
+            script: committed_script,
+            script_hash,
+            limits,
+            challenge,
+            nullifier: None,
+            output: None,
+            disclosed: Vec::new(),
+        }));
+
         return;
     }
 
     // safe to unwrap since we check earlier
     let credentials = credentials_res.unwrap();
+    let cred_roots = cred_roots_res.unwrap();
+    let revocation_roots = revocation_roots_res.unwrap();
     let cred_schemas = credentials.iter().map(|data| data.schema_id).collect();
 
-    // calculate sha256 hash of each credential
-    let cred_hashes: Vec<String> = credentials_str
+    // prove knowledge of the secret all credentials were issued to
+    let holder_commitment = Base64::encode_string(sha::Impl::hash_bytes(inputs.holder_secret.as_bytes()).as_bytes());
+    // get the fields to disclose verbatim
+    let disclosed_res = disclose_fields(&credentials, &inputs.disclose);
+    let raw_result = if credentials.iter().any(|cred| cred.holder_commitment != holder_commitment) {
+        Err("holder secret does not match the credentials".to_string())
+    }
+    // the verifier's time is committed with the challenge, so it can't be picked by the holder
+    else if credentials.iter().any(|cred| !is_valid_at(cred, challenge.issued_at)) {
+        Err("some credential is not valid at the challenge time".to_string())
+    }
+    // make sure the script runs over well-formed data
+    else if let Err(schema_error) = validate_schemas(&credentials, &cred_schema_docs) {
+        Err(format!("credential does not match its schema: {}", schema_error))
+    }
+    else if let Err(disclose_error) = &disclosed_res {
+        Err(disclose_error.clone())
+    }
+    else {
+        // run the script
+        let script_result = match script_lang {
+            ScriptLang::Rhai => run_rhai(&credentials, &input_script, challenge.issued_at, &limits),
+            ScriptLang::JavaScript => run_js(&credentials, &input_script, challenge.issued_at, &limits),
+        };
+        script_result.map_err(|err| format!("script error: {}", err))
+    };
+
+    if raw_result.is_err() {
+        env::commit(&VersionedJournal::from(ZkCommit {
+            has_error: true,
+            err_msg: raw_result.err().unwrap(),
+            cred_roots,
+            revocation_roots,
+            cred_schemas,
+            cred_schema_hashes,
+            lang: inputs.lang,
+            script: committed_script,
+            script_hash,
+            limits,
+            challenge,
+            nullifier: None,
+            output: None,
+            disclosed: Vec::new(),
+        }));
+
+        return;
+    }
+
+    // pseudonymous identifier of the holder, scoped to the verifier
+    let nullifier = challenge.scope.as_ref().map(|scope| {
+        let verifier_scope = format!("{}/{}", challenge.verifier_id, scope);
+        let preimage = [inputs.holder_secret.as_bytes(), verifier_scope.as_bytes()].concat();
+        Base64::encode_string(sha::Impl::hash_bytes(&preimage).as_bytes())
+    });
+
+    env::commit(&VersionedJournal::from(ZkCommit {
+        has_error: false,
+        err_msg: "".to_string(),
+        cred_roots,
+        revocation_roots,
+        cred_schemas,
+        cred_schema_hashes,
+        lang: inputs.lang,
+        // IMPORTANT!! use input script here to not expose credentials
+        script: committed_script,
+        script_hash,
+        limits,
+        challenge,
+        nullifier,
+        output: Some(raw_result.unwrap()),
+        // only the fields requested by the holder, and nothing else
+        disclosed: disclosed_res.unwrap(),
+    }));
+}
+
+fn is_valid_at(credential: &CredentialInstanceData, time: u64) -> bool {
+    credential.valid_from <= time && credential.valid_until.map_or(true, |valid_until| time < valid_until)
+}
+
+// validate the details of each credential against its JSON Schema
+fn validate_schemas(credentials: &[CredentialInstanceData], schemas: &[String]) -> Result<(), String> {
+    if credentials.len() != schemas.len() {
+        return Err("expected one schema per credential".to_string());
+    }
+
+    credentials
         .iter()
-        .map(|cred| Base64::encode_string(sha::Impl::hash_bytes(cred.as_bytes()).as_bytes()))
-        .collect();
+        .zip(schemas)
+        .enumerate()
+        .try_for_each(|(i, (cred_data, schema))| {
+            let schema: Value = from_str(schema).map_err(|err| format!("schema {} is not valid JSON: {}", i, err))?;
+            let details: Value = from_str(&cred_data.details).map_err(|err| format!("credential {} details are not valid JSON: {}", i, err))?;
+            schema::validate(&schema, &details).map_err(|err| format!("credential {} at {}", i, err))
+        })
+}
 
-    
-    match script_lang {
-        ScriptLang::Rhai => {
-            let engine = Engine::new_raw();
-            let mut scope = Scope::new();
-            
-            // inject credentials in the script
-            let rhai_creds: Dynamic = credentials
+// get the JSON encoded value of each field to disclose, per credential
+fn disclose_fields(credentials: &[CredentialInstanceData], disclose: &[Vec<String>]) -> Result<Vec<BTreeMap<String, String>>, String> {
+    // nothing is disclosed by default
+    if disclose.is_empty() {
+        return Ok(Vec::new());
+    }
+    if disclose.len() != credentials.len() {
+        return Err("expected one list of fields to disclose per credential".to_string());
+    }
+
+    credentials
+        .iter()
+        .zip(disclose)
+        .enumerate()
+        .map(|(i, (cred_data, pointers))| -> Result<BTreeMap<String, String>, String> {
+            let details: Value = from_str(&cred_data.details).map_err(|err| err.to_string())?;
+            pointers
                 .iter()
-                .map(|cred_data| engine.parse_json(cred_data.details.clone(), true).unwrap())
-                .collect::<Vec<_>>()
-                .into();
-            scope.push_constant_dynamic("credentials", rhai_creds);
-            
-            // run the script
-            let raw_result = engine.eval_with_scope::<bool>(&mut scope, &input_script);
-
-            if raw_result.is_err() {
-                env::commit(&ZkCommit {
-                    has_error: true,
-                    err_msg: format!("script error: {}", raw_result.err().unwrap()),
-                    cred_hashes,
-                    cred_schemas,
-                    lang: inputs.lang,
-                    script: input_script,
-                    result: false,
-                });
-                
-                return;
-            }
-
-            env::commit(&ZkCommit {
-                has_error: false,
-                err_msg: "".to_string(),
-                cred_hashes,
-                cred_schemas,
-                lang: inputs.lang,
-                // IMPORTANT!! use input script here to not expose credentials
-                script: input_script,
-                result: raw_result.unwrap(),
-            });
-        },
-        ScriptLang::JavaScript => (),
+                .map(|pointer| match details.pointer(pointer) {
+                    Some(value) => Ok((pointer.clone(), to_string(value).unwrap())),
+                    None => Err(format!("credential {} has no field at {}", i, pointer)),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// evaluate a Rhai script over the credentials' details, with the shared helpers.
+// Time dependent helpers use the challenge time
+fn run_rhai(credentials: &[CredentialInstanceData], script: &str, now: u64, limits: &ScriptLimits) -> Result<ScriptValue, String> {
+    let details: Vec<String> = credentials.iter().map(|cred_data| cred_data.details.clone()).collect();
+    rhai_std::eval(script, &details, now, limits)
+}
+
+// Clock of the JavaScript engine: the zkVM has no clock, and dates must not depend on the prover anyway
+struct ChallengeClock {
+    now: u64,
+}
+
+impl HostHooks for ChallengeClock {
+    fn utc_now(&self) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp_opt(self.now as i64, 0).unwrap_or_default()
     }
+
+    // local dates are in UTC, like the dates of the Rhai helpers
+    fn local_timezone_offset_seconds(&self, _unix_time_seconds: i64) -> i32 {
+        0
+    }
+}
+
+// Math.random would make the output depend on the prover's randomness
+const JS_PRELUDE: &str = "Object.defineProperty(Math, 'random', { value: function random() { throw new Error('Math.random is not available in proofs'); } });";
+
+// evaluate a JavaScript script over the credentials' details.
+// Dates (e.g. Date.now()) are at the challenge time, same as in the Rhai helpers
+fn run_js(credentials: &[CredentialInstanceData], script: &str, now: u64, limits: &ScriptLimits) -> Result<ScriptValue, String> {
+    // the limits are committed, so they must not claim what boa can't enforce
+    if limits.max_operations != 0 || limits.max_string_size != 0 || limits.max_collection_size != 0 {
+        return Err("JavaScript scripts can't be limited in operations, string or collection size, these limits must be 0".to_string());
+    }
+
+    let clock = ChallengeClock { now };
+    let mut context = Context::builder()
+        .host_hooks(&clock)
+        .build()
+        .map_err(|err| err.to_string())?;
+    context
+        .eval(Source::from_bytes(JS_PRELUDE))
+        .map_err(|err| err.to_string())?;
+    context.runtime_limits_mut().set_recursion_limit(limits.max_call_depth as usize);
+
+    // inject credentials in the script
+    let json_creds: Vec<Value> = credentials
+        .iter()
+        .map(|cred_data| from_str(&cred_data.details))
+        .collect::<Result<_, _>>()
+        .map_err(|err| err.to_string())?;
+    let js_creds = JsValue::from_json(&Value::Array(json_creds), &mut context)
+        .map_err(|err| err.to_string())?;
+    context
+        .register_global_property("credentials", js_creds, Attribute::READONLY)
+        .map_err(|err| err.to_string())?;
+
+    // the script's completion value is its output
+    let output = context
+        .eval(Source::from_bytes(script))
+        .map_err(|err| err.to_string())?
+        .to_json(&mut context)
+        .map_err(|err| err.to_string())?;
+    ScriptValue::from_json(&output)
 }
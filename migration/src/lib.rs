@@ -3,6 +3,15 @@ pub use sea_orm_migration::prelude::*;
 mod m20231228_182440_create_holder_table;
 mod m20231228_183743_create_credential_table;
 mod m20231228_185744_create_credential_instance_table;
+mod m20240110_141205_add_holder_commitment;
+mod m20240118_093412_add_credential_instance_revoked;
+mod m20240125_160233_create_proof_job_table;
+mod m20240202_112817_add_proof_job_timings;
+mod m20240207_153020_add_proof_job_input_digest;
+mod m20240214_101533_add_credential_instance_validity;
+mod m20240221_094617_add_proof_job_segments;
+mod m20240228_135208_create_nullifier_table;
+mod m20240301_102745_add_holder_credential_removed;
 
 
 pub struct Migrator;
@@ -14,6 +23,15 @@ impl MigratorTrait for Migrator {
             Box::new(m20231228_182440_create_holder_table::Migration),
             Box::new(m20231228_183743_create_credential_table::Migration),
             Box::new(m20231228_185744_create_credential_instance_table::Migration),
+            Box::new(m20240110_141205_add_holder_commitment::Migration),
+            Box::new(m20240118_093412_add_credential_instance_revoked::Migration),
+            Box::new(m20240125_160233_create_proof_job_table::Migration),
+            Box::new(m20240202_112817_add_proof_job_timings::Migration),
+            Box::new(m20240207_153020_add_proof_job_input_digest::Migration),
+            Box::new(m20240214_101533_add_credential_instance_validity::Migration),
+            Box::new(m20240221_094617_add_proof_job_segments::Migration),
+            Box::new(m20240228_135208_create_nullifier_table::Migration),
+            Box::new(m20240301_102745_add_holder_credential_removed::Migration),
         ]
     }
 }
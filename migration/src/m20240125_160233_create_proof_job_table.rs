@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProofJob::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ProofJob::Id)
+                            .unsigned()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ProofJob::Input).text().not_null())
+                    .col(ColumnDef::new(ProofJob::State).string_len(16).not_null())
+                    .col(ColumnDef::new(ProofJob::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(ProofJob::StartedAt).big_integer())
+                    .col(ColumnDef::new(ProofJob::FinishedAt).big_integer())
+                    .col(ColumnDef::new(ProofJob::Cycles).big_integer())
+                    .col(ColumnDef::new(ProofJob::Receipt).binary())
+                    .col(ColumnDef::new(ProofJob::Error).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        // the workers look up jobs by state, in queue order
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-proof_job-state")
+                    .table(ProofJob::Table)
+                    .col(ProofJob::State)
+                    .col(ProofJob::Id)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ProofJob::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ProofJob {
+    Table,
+    Id,
+    Input,
+    State,
+    CreatedAt,
+    StartedAt,
+    FinishedAt,
+    Cycles,
+    Receipt,
+    Error,
+}
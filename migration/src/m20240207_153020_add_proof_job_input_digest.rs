@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ProofJob::Table)
+                    .add_column(ColumnDef::new(ProofJob::InputDigest).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // cached proofs are looked up by input digest
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-proof_job-input_digest")
+                    .table(ProofJob::Table)
+                    .col(ProofJob::InputDigest)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-proof_job-input_digest")
+                    .table(ProofJob::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ProofJob::Table)
+                    .drop_column(ProofJob::InputDigest)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ProofJob {
+    Table,
+    InputDigest,
+}
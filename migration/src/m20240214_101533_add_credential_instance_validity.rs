@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// SQLite only supports one column per ALTER TABLE statement
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CredentialInstance::Table)
+                    .add_column(ColumnDef::new(CredentialInstance::ValidFrom).big_integer().not_null().default(0))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CredentialInstance::Table)
+                    .add_column(ColumnDef::new(CredentialInstance::ValidUntil).big_integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CredentialInstance::Table)
+                    .drop_column(CredentialInstance::ValidUntil)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CredentialInstance::Table)
+                    .drop_column(CredentialInstance::ValidFrom)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CredentialInstance {
+    Table,
+    ValidFrom,
+    ValidUntil,
+}
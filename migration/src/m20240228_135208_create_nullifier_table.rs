@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Nullifier::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Nullifier::Id)
+                            .unsigned()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Nullifier::Scope).string().not_null())
+                    .col(ColumnDef::new(Nullifier::Nullifier).string().not_null())
+                    .col(ColumnDef::new(Nullifier::CreatedAt).big_integer().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // a holder presents at most once per scope
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-nullifier-scope-nullifier")
+                    .table(Nullifier::Table)
+                    .col(Nullifier::Scope)
+                    .col(Nullifier::Nullifier)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Nullifier::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Nullifier {
+    Table,
+    Id,
+    Scope,
+    Nullifier,
+    CreatedAt,
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Removed holders and credentials are kept, as deleting them would cascade to credential instances
+// that must stay revoked in the registry
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Holder::Table)
+                    .add_column(ColumnDef::new(Holder::Removed).boolean().not_null().default(false))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Credential::Table)
+                    .add_column(ColumnDef::new(Credential::Removed).boolean().not_null().default(false))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Credential::Table)
+                    .drop_column(Credential::Removed)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Holder::Table)
+                    .drop_column(Holder::Removed)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Holder {
+    Table,
+    Removed,
+}
+
+#[derive(DeriveIden)]
+enum Credential {
+    Table,
+    Removed,
+}
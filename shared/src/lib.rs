@@ -1 +1,5 @@
-pub mod types;
\ No newline at end of file
+pub mod types;
+pub mod merkle;
+pub mod schema;
+#[cfg(feature = "rhai")]
+pub mod rhai_std;
\ No newline at end of file
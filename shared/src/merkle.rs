@@ -0,0 +1,358 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use base64ct::{Base64, Encoding};
+
+
+pub type Hash = [u8; 32];
+
+// Root of a tree without leaves
+pub const EMPTY_ROOT: Hash = [0; 32];
+
+// Leaves and inner nodes are hashed with different prefixes,
+// so an inner node can never be passed off as a leaf
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+pub fn hash_leaf(value: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([LEAF_PREFIX])
+        .chain_update(value)
+        .finalize()
+        .into()
+}
+
+pub fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([NODE_PREFIX])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+pub fn encode_hash(hash: &Hash) -> String {
+    Base64::encode_string(hash)
+}
+
+pub fn decode_hash(encoded: &str) -> Option<Hash> {
+    Base64::decode_vec(encoded).ok()?.try_into().ok()
+}
+
+// Inclusion proof of a leaf in a MerkleTree
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MerklePath {
+    // position of the leaf in the tree
+    pub index: u64,
+    // base64 encoded sibling hashes, from the leaf level up to the root
+    pub siblings: Vec<String>,
+}
+
+impl MerklePath {
+    /// Returns the root of the tree the leaf is included in, according to this path.
+    /// Returns None if some sibling is not a valid hash.
+    pub fn root(&self, value: &[u8]) -> Option<Hash> {
+        let mut node = hash_leaf(value);
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            let sibling = decode_hash(sibling)?;
+            // the index bit of each level tells whether the node is a left or right child
+            node = if (self.index >> level) & 1 == 0 {
+                hash_node(&node, &sibling)
+            } else {
+                hash_node(&sibling, &node)
+            };
+        }
+
+        Some(node)
+    }
+}
+
+/// Binary Merkle tree over sha256.
+/// Levels with an odd number of nodes are padded by repeating their last node,
+/// so every leaf has a sibling at each level and paths have the same length.
+pub struct MerkleTree {
+    num_leaves: usize,
+    // levels[0] are the hashed leaves, the last level holds the root
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(values: &[Hash]) -> Self {
+        let mut levels: Vec<Vec<Hash>> = vec![values.iter().map(|value| hash_leaf(value)).collect()];
+        while levels[levels.len() - 1].len() > 1 {
+            let mut level = levels[levels.len() - 1].clone();
+            if level.len() % 2 == 1 {
+                level.push(level[level.len() - 1]);
+            }
+            let parents = level
+                .chunks(2)
+                .map(|pair| hash_node(&pair[0], &pair[1]))
+                .collect();
+            let last = levels.len() - 1;
+            levels[last] = level;
+            levels.push(parents);
+        }
+
+        Self { num_leaves: values.len(), levels }
+    }
+
+    pub fn root(&self) -> Hash {
+        match self.levels[self.levels.len() - 1].first() {
+            Some(root) => *root,
+            None => EMPTY_ROOT,
+        }
+    }
+
+    /// Returns the inclusion proof of the leaf at the given index, if it exists
+    pub fn path(&self, index: usize) -> Option<MerklePath> {
+        if index >= self.num_leaves {
+            return None;
+        }
+
+        let siblings = self.levels[..self.levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(level, nodes)| encode_hash(&nodes[(index >> level) ^ 1]))
+            .collect();
+
+        Some(MerklePath { index: index as u64, siblings })
+    }
+}
+
+// Sentinel leaves bounding every SortedMerkleTree, so any other value falls between two adjacent leaves
+pub const MIN_LEAF: Hash = [0; 32];
+pub const MAX_LEAF: Hash = [0xff; 32];
+
+// Proof that a value is not a leaf of a SortedMerkleTree:
+// two adjacent leaves, one lower and one higher than the value
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NonMembershipProof {
+    // base64 encoded leaf values
+    pub low: String,
+    pub low_path: MerklePath,
+    pub high: String,
+    pub high_path: MerklePath,
+}
+
+impl NonMembershipProof {
+    /// Returns the root of the sorted tree the value is proven not to be in.
+    /// Returns None if the proof is not valid for this value.
+    pub fn root(&self, value: &Hash) -> Option<Hash> {
+        let low = decode_hash(&self.low)?;
+        let high = decode_hash(&self.high)?;
+        let low_root = self.low_path.root(&low)?;
+        let high_root = self.high_path.root(&high)?;
+
+        let is_valid = low < *value && *value < high
+            // both leaves are in the same tree...
+            && low_root == high_root
+            && self.low_path.siblings.len() == self.high_path.siblings.len()
+            // ...next to each other
+            && self.low_path.index.checked_add(1) == Some(self.high_path.index);
+
+        if is_valid { Some(low_root) } else { None }
+    }
+}
+
+/// MerkleTree over sorted and deduplicated values, supporting proofs of non-membership
+pub struct SortedMerkleTree {
+    // sorted leaf values, including the sentinels
+    values: Vec<Hash>,
+    tree: MerkleTree,
+}
+
+impl SortedMerkleTree {
+    pub fn new(values: &[Hash]) -> Self {
+        let mut sorted_values: Vec<Hash> = Vec::with_capacity(values.len() + 2);
+        sorted_values.push(MIN_LEAF);
+        sorted_values.extend(values.iter().filter(|value| **value != MIN_LEAF && **value != MAX_LEAF));
+        sorted_values.push(MAX_LEAF);
+        sorted_values.sort_unstable();
+        sorted_values.dedup();
+
+        let tree = MerkleTree::new(&sorted_values);
+        Self { values: sorted_values, tree }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    /// Returns a proof that the value is not in the tree, or None if it is
+    pub fn non_membership(&self, value: &Hash) -> Option<NonMembershipProof> {
+        // index of the first leaf higher than the value (never 0, thanks to MIN_LEAF)
+        let high_index = match self.values.binary_search(value) {
+            Ok(_) => return None,
+            Err(index) => index,
+        };
+        let low_index = high_index - 1;
+
+        Some(NonMembershipProof {
+            low: encode_hash(&self.values[low_index]),
+            low_path: self.tree.path(low_index)?,
+            high: encode_hash(&self.values[high_index]),
+            high_path: self.tree.path(high_index)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(num_values: u8) -> Vec<Hash> {
+        (1..=num_values).map(|i| [i; 32]).collect()
+    }
+
+    #[test]
+    fn paths_prove_every_leaf_at_any_size() {
+        for num_leaves in 1..=11 {
+            let leaves = values(num_leaves);
+            let tree = MerkleTree::new(&leaves);
+            let depth = tree.path(0).unwrap().siblings.len();
+            for (index, leaf) in leaves.iter().enumerate() {
+                let path = tree.path(index).unwrap();
+                assert_eq!(path.siblings.len(), depth, "{num_leaves} leaves, leaf {index}");
+                assert_eq!(path.root(leaf), Some(tree.root()), "{num_leaves} leaves, leaf {index}");
+            }
+            assert!(tree.path(num_leaves as usize).is_none());
+        }
+    }
+
+    #[test]
+    fn odd_levels_are_padded_with_their_last_node() {
+        let leaves = values(3);
+        let tree = MerkleTree::new(&leaves);
+        let left = hash_node(&hash_leaf(&leaves[0]), &hash_leaf(&leaves[1]));
+        let right = hash_node(&hash_leaf(&leaves[2]), &hash_leaf(&leaves[2]));
+        assert_eq!(tree.root(), hash_node(&left, &right));
+    }
+
+    #[test]
+    fn empty_tree_has_empty_root() {
+        let tree = MerkleTree::new(&[]);
+        assert_eq!(tree.root(), EMPTY_ROOT);
+        assert!(tree.path(0).is_none());
+    }
+
+    #[test]
+    fn paths_reject_other_leaves() {
+        let leaves = values(5);
+        let tree = MerkleTree::new(&leaves);
+        assert_ne!(tree.path(1).unwrap().root(&leaves[2]), Some(tree.root()));
+    }
+
+    #[test]
+    fn non_membership_of_values_between_leaves() {
+        for num_values in 0..=9 {
+            let leaves: Vec<Hash> = values(num_values).iter().map(|value| [value[0] * 2; 32]).collect();
+            let tree = SortedMerkleTree::new(&leaves);
+            // [0; 32] is MIN_LEAF
+            for i in 1..=num_values * 2 + 1 {
+                let value = [i; 32];
+                let proof = tree.non_membership(&value);
+                if leaves.contains(&value) {
+                    assert!(proof.is_none());
+                } else {
+                    assert_eq!(proof.unwrap().root(&value), Some(tree.root()), "{num_values} values, value {i}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn non_membership_is_order_independent() {
+        let leaves = values(6);
+        let mut shuffled = leaves.clone();
+        shuffled.reverse();
+        shuffled.push(leaves[2]);
+        assert_eq!(SortedMerkleTree::new(&leaves).root(), SortedMerkleTree::new(&shuffled).root());
+    }
+
+    #[test]
+    fn non_membership_rejects_non_adjacent_leaves() {
+        let leaves: Vec<Hash> = vec![[2; 32], [4; 32], [6; 32]];
+        let tree = SortedMerkleTree::new(&leaves);
+        // [4; 32] lies between [2; 32] and [6; 32], skipping over the leaf itself
+        let proof = NonMembershipProof {
+            low: encode_hash(&leaves[0]),
+            low_path: tree.tree.path(1).unwrap(),
+            high: encode_hash(&leaves[2]),
+            high_path: tree.tree.path(3).unwrap(),
+        };
+        assert_eq!(proof.root(&[4; 32]), None);
+    }
+
+    #[test]
+    fn non_membership_rejects_out_of_order_leaves() {
+        let tree = SortedMerkleTree::new(&values(4));
+        // proven between [1; 32] and [2; 32]
+        let mut value = [1; 32];
+        value[31] = 2;
+        let proof = tree.non_membership(&value).unwrap();
+        assert_eq!(proof.root(&value), Some(tree.root()));
+        // values higher or lower than both leaves
+        assert_eq!(proof.root(&[3; 32]), None);
+        let mut lower = [0; 32];
+        lower[31] = 1;
+        assert_eq!(proof.root(&lower), None);
+        // leaves swapped
+        let swapped = NonMembershipProof {
+            low: proof.high.clone(),
+            low_path: proof.high_path.clone(),
+            high: proof.low.clone(),
+            high_path: proof.low_path.clone(),
+        };
+        assert_eq!(swapped.root(&value), None);
+    }
+
+    #[test]
+    fn non_membership_rejects_the_leaves_themselves() {
+        let tree = SortedMerkleTree::new(&values(4));
+        let proof = tree.non_membership(&[0x10; 32]).unwrap();
+        let low = decode_hash(&proof.low).unwrap();
+        let high = decode_hash(&proof.high).unwrap();
+        assert_eq!(proof.root(&low), None);
+        assert_eq!(proof.root(&high), None);
+    }
+
+    #[test]
+    fn sentinels_bound_the_tree() {
+        // sentinel values are never leaves besides the sentinels themselves, and can't be proven absent
+        let tree = SortedMerkleTree::new(&[MIN_LEAF, [1; 32], MAX_LEAF]);
+        assert_eq!(tree.values, vec![MIN_LEAF, [1; 32], MAX_LEAF]);
+        assert!(tree.non_membership(&MIN_LEAF).is_none());
+        assert!(tree.non_membership(&MAX_LEAF).is_none());
+
+        // values next to the sentinels are proven by them
+        let mut below_max = MAX_LEAF;
+        below_max[31] = 0xfe;
+        let proof = tree.non_membership(&below_max).unwrap();
+        assert_eq!(proof.high, encode_hash(&MAX_LEAF));
+        assert_eq!(proof.root(&below_max), Some(tree.root()));
+        let mut above_min = MIN_LEAF;
+        above_min[31] = 1;
+        let proof = tree.non_membership(&above_min).unwrap();
+        assert_eq!(proof.low, encode_hash(&MIN_LEAF));
+        assert_eq!(proof.root(&above_min), Some(tree.root()));
+
+        // a sentinel proof can't be reused for the sentinels themselves
+        assert_eq!(proof.root(&MIN_LEAF), None);
+    }
+
+    #[test]
+    fn non_membership_rejects_the_padding_leaf() {
+        // 3 leaves with the sentinels: MAX_LEAF is repeated as the 6th leaf
+        let tree = SortedMerkleTree::new(&values(3));
+        assert_eq!(tree.values.len(), 5);
+        let max_path = tree.tree.path(4).unwrap();
+        let mut padding_path = max_path.clone();
+        padding_path.index = 5;
+        let proof = NonMembershipProof {
+            low: encode_hash(&MAX_LEAF),
+            low_path: max_path,
+            high: encode_hash(&MAX_LEAF),
+            high_path: padding_path,
+        };
+        assert_eq!(proof.root(&MAX_LEAF), None);
+    }
+}
@@ -0,0 +1,285 @@
+use crate::types::{ScriptLimits, ScriptValue};
+use rhai::{
+    Engine, Scope, Dynamic, Array, Map, EvalAltResult, INT, FLOAT,
+    packages::{
+        Package, CorePackage, LogicPackage, BasicMathPackage, BasicArrayPackage, BasicMapPackage, MoreStringPackage,
+    },
+};
+
+
+// Member states of the European Union, as ISO 3166-1 alpha-2 codes
+pub const EU_COUNTRIES: [&str; 27] = [
+    "AT", "BE", "BG", "CY", "CZ", "DE", "DK", "EE", "ES", "FI", "FR", "GR", "HR", "HU",
+    "IE", "IT", "LT", "LU", "LV", "MT", "NL", "PL", "PT", "RO", "SE", "SI", "SK",
+];
+// Non-EU members of the European Economic Area
+pub const EEA_ONLY_COUNTRIES: [&str; 3] = ["IS", "LI", "NO"];
+
+// Description of the helpers, for script generation prompts
+pub const HELPERS_DOC: &str = r#"Dates are "YYYY-MM-DD" strings.
+parse_date(date) -> days since 1970-01-01; today() -> current date; days_between(from, to) -> days;
+age(birthdate) -> full years as of today; age_at(birthdate, date) -> full years as of date;
+is_eu_country(code), is_eea_country(code) -> bool for ISO 3166-1 alpha-2 codes; eu_countries() -> array of codes;
+normalize(string) -> trimmed lowercase string with single spaces; eq_ignore_case(a, b) -> bool;
+num(value) -> float from an integer, float or numeric string; at_least(a, b), at_most(a, b), between(value, min, max) -> bool on num values."#;
+
+/// Rhai engine used to evaluate scripts, with a curated set of deterministic packages and helpers.
+/// Time dependent helpers (today, age) use `now` (UNIX timestamp in seconds) instead of the system clock,
+/// so results are the same inside the zkVM and on the host
+pub fn engine(now: u64, limits: &ScriptLimits) -> Engine {
+    let mut engine = Engine::new_raw();
+    engine.set_max_operations(limits.max_operations);
+    engine.set_max_call_levels(limits.max_call_depth as usize);
+    engine.set_max_string_size(limits.max_string_size as usize);
+    engine.set_max_array_size(limits.max_collection_size as usize);
+    engine.set_max_map_size(limits.max_collection_size as usize);
+
+    // the standard package is not used, as its time functions read the system clock
+    engine.register_global_module(CorePackage::new().as_shared_module());
+    engine.register_global_module(LogicPackage::new().as_shared_module());
+    engine.register_global_module(BasicMathPackage::new().as_shared_module());
+    engine.register_global_module(BasicArrayPackage::new().as_shared_module());
+    engine.register_global_module(BasicMapPackage::new().as_shared_module());
+    engine.register_global_module(MoreStringPackage::new().as_shared_module());
+
+    let today = (now / (24 * 60 * 60)) as INT;
+
+    // dates
+    engine.register_fn("parse_date", |date: &str| parse_date(date));
+    engine.register_fn("today", move || format_date(today));
+    engine.register_fn("days_between", |from: &str, to: &str| Ok::<_, Box<EvalAltResult>>(parse_date(to)? - parse_date(from)?));
+    engine.register_fn("age", move |birthdate: &str| years_between(parse_date(birthdate)?, today));
+    engine.register_fn("age_at", |birthdate: &str, date: &str| years_between(parse_date(birthdate)?, parse_date(date)?));
+
+    // countries
+    engine.register_fn("is_eu_country", |code: &str| EU_COUNTRIES.contains(&code.trim().to_uppercase().as_str()));
+    engine.register_fn("is_eea_country", |code: &str| {
+        let code = code.trim().to_uppercase();
+        EU_COUNTRIES.contains(&code.as_str()) || EEA_ONLY_COUNTRIES.contains(&code.as_str())
+    });
+    engine.register_fn("eu_countries", || EU_COUNTRIES.iter().map(|code| Dynamic::from(code.to_string())).collect::<Array>());
+
+    // strings
+    engine.register_fn("normalize", |string: &str| normalize(string));
+    engine.register_fn("eq_ignore_case", |a: &str, b: &str| normalize(a) == normalize(b));
+
+    // numbers, credentials may hold integers, floats or numeric strings
+    engine.register_fn("num", num);
+    engine.register_fn("at_least", |a: Dynamic, b: Dynamic| Ok::<_, Box<EvalAltResult>>(num(a)? >= num(b)?));
+    engine.register_fn("at_most", |a: Dynamic, b: Dynamic| Ok::<_, Box<EvalAltResult>>(num(a)? <= num(b)?));
+    engine.register_fn("between", |value: Dynamic, min: Dynamic, max: Dynamic| {
+        let value = num(value)?;
+        Ok::<_, Box<EvalAltResult>>(num(min)? <= value && value <= num(max)?)
+    });
+
+    engine
+}
+
+/// Evaluates a Rhai script over the credentials' details (stringified JSON objects),
+/// which are available to the script in the constant "credentials" array
+pub fn eval(script: &str, credentials_details: &[String], now: u64, limits: &ScriptLimits) -> Result<ScriptValue, String> {
+    let engine = engine(now, limits);
+    let mut scope = Scope::new();
+
+    // inject credentials in the script
+    let rhai_creds: Dynamic = credentials_details
+        .iter()
+        .map(|details| engine.parse_json(details, true).map(Dynamic::from))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?
+        .into();
+    scope.push_constant_dynamic("credentials", rhai_creds);
+
+    let output = engine
+        .eval_with_scope::<Dynamic>(&mut scope, script)
+        .map_err(|err| err.to_string())?;
+    to_script_value(output, 0)
+}
+
+// Converts the value returned by a script, maps can only hold primitive values
+fn to_script_value(value: Dynamic, depth: usize) -> Result<ScriptValue, String> {
+    let type_name = value.type_name();
+    if let Ok(boolean) = value.as_bool() {
+        Ok(ScriptValue::Bool(boolean))
+    }
+    else if let Ok(int) = value.as_int() {
+        Ok(ScriptValue::Int(int))
+    }
+    else if value.is_string() {
+        Ok(ScriptValue::Str(value.into_string().unwrap()))
+    }
+    else if value.is_map() && depth == 0 {
+        value
+            .cast::<Map>()
+            .into_iter()
+            .map(|(key, value)| Ok((key.to_string(), to_script_value(value, depth + 1)?)))
+            .collect::<Result<_, String>>()
+            .map(ScriptValue::Map)
+    }
+    else {
+        Err(format!("script output must be a boolean, an integer, a string, or a map of them, not {}", type_name))
+    }
+}
+
+// Days since 1970-01-01 of a "YYYY-MM-DD" date
+fn parse_date(date: &str) -> Result<INT, Box<EvalAltResult>> {
+    let invalid = || -> Box<EvalAltResult> { format!("invalid date \"{}\", expected YYYY-MM-DD", date).into() };
+
+    let parts: Vec<&str> = date.trim().split('-').collect();
+    let [year, month, day] = parts.as_slice() else { return Err(invalid()) };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return Err(invalid());
+    }
+    let year: INT = year.parse().map_err(|_| invalid())?;
+    let month: INT = month.parse().map_err(|_| invalid())?;
+    let day: INT = day.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+
+    Ok(days_from_civil(year, month, day))
+}
+
+fn format_date(days: INT) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Full years elapsed between two dates (in days since 1970-01-01)
+fn years_between(from: INT, to: INT) -> Result<INT, Box<EvalAltResult>> {
+    let (from_year, from_month, from_day) = civil_from_days(from);
+    let (to_year, to_month, to_day) = civil_from_days(to);
+    let before_anniversary = (to_month, to_day) < (from_month, from_day);
+
+    Ok(to_year - from_year - if before_anniversary { 1 } else { 0 })
+}
+
+fn days_in_month(year: INT, month: INT) -> INT {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date (see http://howardhinnant.github.io/date_algorithms.html)
+fn days_from_civil(year: INT, month: INT, day: INT) -> INT {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: INT) -> (INT, INT, INT) {
+    let days = days + 719468;
+    let era = (if days >= 0 { days } else { days - 146096 }) / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn normalize(string: &str) -> String {
+    string.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn num(value: Dynamic) -> Result<FLOAT, Box<EvalAltResult>> {
+    if let Ok(int) = value.as_int() {
+        return Ok(int as FLOAT);
+    }
+    if let Ok(float) = value.as_float() {
+        return Ok(float);
+    }
+    let type_name = value.type_name();
+    match value.into_string() {
+        Ok(string) => string.trim().parse().map_err(|_| format!("\"{}\" is not a number", string).into()),
+        Err(_) => Err(format!("{} is not a number", type_name).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        for days in [-719468, -1, 0, 11016, 11017, 19782, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(format_date(parse_date("2024-02-29").unwrap()), "2024-02-29");
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("1900-02-29").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-1-01").is_err());
+        assert!(parse_date("01/01/2024").is_err());
+    }
+
+    #[test]
+    fn years_between_dates() {
+        let years = |from: &str, to: &str| years_between(parse_date(from).unwrap(), parse_date(to).unwrap()).unwrap();
+        assert_eq!(years("2000-06-15", "2018-06-14"), 17);
+        assert_eq!(years("2000-06-15", "2018-06-15"), 18);
+        assert_eq!(years("2000-06-15", "2000-06-15"), 0);
+        // leap-day birthdays are only reached on March 1st in common years
+        assert_eq!(years("2004-02-29", "2022-02-28"), 17);
+        assert_eq!(years("2004-02-29", "2022-03-01"), 18);
+        assert_eq!(years("2004-02-29", "2024-02-29"), 20);
+    }
+
+    #[test]
+    fn num_values() {
+        assert_eq!(num(Dynamic::from(42 as INT)).unwrap(), 42.0);
+        assert_eq!(num(Dynamic::from(4.5 as FLOAT)).unwrap(), 4.5);
+        assert_eq!(num(Dynamic::from(" -7.25 ".to_string())).unwrap(), -7.25);
+        assert!(num(Dynamic::from("seven".to_string())).is_err());
+        assert!(num(Dynamic::from(true)).is_err());
+    }
+
+    #[test]
+    fn script_values() {
+        assert!(matches!(to_script_value(Dynamic::from(true), 0), Ok(ScriptValue::Bool(true))));
+        assert!(matches!(to_script_value(Dynamic::from(7 as INT), 0), Ok(ScriptValue::Int(7))));
+        assert!(matches!(to_script_value(Dynamic::from("a".to_string()), 0), Ok(ScriptValue::Str(string)) if string == "a"));
+
+        let mut map = Map::new();
+        map.insert("adult".into(), Dynamic::from(true));
+        let Ok(ScriptValue::Map(map)) = to_script_value(Dynamic::from(map), 0) else { panic!("expected a map") };
+        assert!(matches!(map.get("adult"), Some(ScriptValue::Bool(true))));
+
+        // maps only hold primitive values
+        let mut nested = Map::new();
+        nested.insert("inner".into(), Dynamic::from(Map::new()));
+        assert!(to_script_value(Dynamic::from(nested), 0).is_err());
+        assert!(to_script_value(Dynamic::from(Array::new()), 0).is_err());
+        assert!(to_script_value(Dynamic::from(1.5 as FLOAT), 0).is_err());
+    }
+
+    #[test]
+    fn evaluates_over_credentials() {
+        let credentials = vec![r#"{"birthdate": "2006-03-01", "country": "de"}"#.to_string()];
+        // 2024-02-29T12:00:00Z
+        let now = 1709208000;
+        let script = r#"age(credentials[0].birthdate) >= 18 && is_eu_country(credentials[0].country)"#;
+        assert!(matches!(eval(script, &credentials, now, &ScriptLimits::default()), Ok(ScriptValue::Bool(false))));
+        let script = r#"age_at(credentials[0].birthdate, "2024-03-01")"#;
+        assert!(matches!(eval(script, &credentials, now, &ScriptLimits::default()), Ok(ScriptValue::Int(18))));
+    }
+}
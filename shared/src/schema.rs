@@ -0,0 +1,273 @@
+use serde_json::{Map, Value};
+use sha2::{Sha256, Digest};
+use base64ct::{Base64, Encoding};
+
+
+/// Base64 encoded sha256 of a schema, as committed in the ZKP journal
+pub fn schema_hash(schema: &str) -> String {
+    Base64::encode_string(&Sha256::digest(schema.as_bytes()))
+}
+
+// Keywords checked by `validate`
+const SUPPORTED_KEYWORDS: [&str; 15] = [
+    "type", "enum", "const",
+    "minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum", "minLength", "maxLength",
+    "items", "minItems", "maxItems", "properties", "required", "additionalProperties",
+];
+// Keywords that do not constrain values
+const ANNOTATION_KEYWORDS: [&str; 10] = [
+    "$schema", "$id", "$comment", "title", "description", "default", "examples", "deprecated", "readOnly", "writeOnly",
+];
+
+/// Validates a JSON value against a JSON Schema.
+/// Only a subset of the specification is supported: type, enum, const,
+/// minimum, maximum, exclusiveMinimum, exclusiveMaximum, minLength, maxLength,
+/// items, minItems, maxItems, properties, required and additionalProperties.
+/// Schemas using any other keyword (besides annotations) are rejected, rather than partially enforced.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), String> {
+    check_keywords(schema, "")?;
+    validate_at(schema, value, "")
+}
+
+// Fails on the first unsupported keyword of the schema or its subschemas.
+// `pointer` is the JSON pointer of the schema being checked, used in error messages
+fn check_keywords(schema: &Value, pointer: &str) -> Result<(), String> {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        _ => return Ok(()),
+    };
+
+    for (keyword, subschema) in schema {
+        if !SUPPORTED_KEYWORDS.contains(&keyword.as_str()) && !ANNOTATION_KEYWORDS.contains(&keyword.as_str()) {
+            return Err(format!("schema {}: unsupported keyword \"{}\"", location(pointer), keyword));
+        }
+        match (keyword.as_str(), subschema) {
+            ("items" | "additionalProperties", _) => check_keywords(subschema, &format!("{}/{}", pointer, keyword))?,
+            ("properties", Value::Object(properties)) => {
+                for (key, property_schema) in properties {
+                    let property_pointer = format!("{}/properties/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                    check_keywords(property_schema, &property_pointer)?;
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+// `pointer` is the JSON pointer of the value being validated, used in error messages
+fn validate_at(schema: &Value, value: &Value, pointer: &str) -> Result<(), String> {
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return Err(format!("{}: no value is allowed", location(pointer))),
+        Value::Object(schema) => schema,
+        _ => return Err(format!("{}: schema must be an object or a boolean", location(pointer))),
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let matches = match expected {
+            Value::String(name) => has_type(value, name),
+            Value::Array(names) => names.iter().any(|name| name.as_str().is_some_and(|name| has_type(value, name))),
+            _ => false,
+        };
+        if !matches {
+            return Err(format!("{}: expected type {}", location(pointer), expected));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return Err(format!("{}: value is not one of {}", location(pointer), Value::Array(allowed.clone())));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            return Err(format!("{}: value must be {}", location(pointer), constant));
+        }
+    }
+
+    match value {
+        Value::Number(number) => validate_number(schema, number.as_f64().unwrap_or(f64::NAN), pointer),
+        Value::String(string) => validate_length(schema, string.chars().count(), "minLength", "maxLength", pointer),
+        Value::Array(items) => {
+            validate_length(schema, items.len(), "minItems", "maxItems", pointer)?;
+            match schema.get("items") {
+                Some(item_schema) => items
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(i, item)| validate_at(item_schema, item, &format!("{}/{}", pointer, i))),
+                None => Ok(()),
+            }
+        },
+        Value::Object(object) => validate_object(schema, object, pointer),
+        _ => Ok(()),
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("null", Value::Null)
+        | ("boolean", Value::Bool(_))
+        | ("number", Value::Number(_))
+        | ("string", Value::String(_))
+        | ("array", Value::Array(_))
+        | ("object", Value::Object(_)) => true,
+        ("integer", Value::Number(number)) => number.is_i64() || number.is_u64() || number.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => false,
+    }
+}
+
+fn validate_number(schema: &Map<String, Value>, number: f64, pointer: &str) -> Result<(), String> {
+    let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+
+    if bound("minimum").is_some_and(|min| number < min)
+        || bound("maximum").is_some_and(|max| number > max)
+        || bound("exclusiveMinimum").is_some_and(|min| number <= min)
+        || bound("exclusiveMaximum").is_some_and(|max| number >= max) {
+        return Err(format!("{}: number is out of range", location(pointer)));
+    }
+
+    Ok(())
+}
+
+fn validate_length(schema: &Map<String, Value>, length: usize, min_keyword: &str, max_keyword: &str, pointer: &str) -> Result<(), String> {
+    if let Some(min) = schema.get(min_keyword).and_then(Value::as_u64) {
+        if (length as u64) < min {
+            return Err(format!("{}: length must be at least {}", location(pointer), min));
+        }
+    }
+    if let Some(max) = schema.get(max_keyword).and_then(Value::as_u64) {
+        if (length as u64) > max {
+            return Err(format!("{}: length must be at most {}", location(pointer), max));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_object(schema: &Map<String, Value>, object: &Map<String, Value>, pointer: &str) -> Result<(), String> {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                return Err(format!("{}: missing required property \"{}\"", location(pointer), key));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, property) in object {
+        let property_pointer = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+        match properties.and_then(|properties| properties.get(key)) {
+            Some(property_schema) => validate_at(property_schema, property, &property_pointer)?,
+            // properties not listed in the schema are allowed, unless additionalProperties says otherwise
+            None => {
+                if let Some(additional_schema) = schema.get("additionalProperties") {
+                    validate_at(additional_schema, property, &property_pointer)?;
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn location(pointer: &str) -> &str {
+    if pointer.is_empty() { "/" } else { pointer }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn person_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": "integer", "minimum": 0, "exclusiveMaximum": 150 },
+                "score": { "type": "number", "exclusiveMinimum": 0, "maximum": 10 },
+            },
+            "required": ["name", "age"],
+            "additionalProperties": false,
+        })
+    }
+
+    #[test]
+    fn accepts_valid_values() {
+        let schema = person_schema();
+        assert_eq!(validate(&schema, &json!({ "name": "Alice", "age": 30 })), Ok(()));
+        assert_eq!(validate(&schema, &json!({ "name": "Bob", "age": 0, "score": 10 })), Ok(()));
+        assert_eq!(validate(&json!(true), &json!({ "anything": [1, 2] })), Ok(()));
+    }
+
+    #[test]
+    fn rejects_missing_required_properties() {
+        let error = validate(&person_schema(), &json!({ "name": "Alice" })).unwrap_err();
+        assert_eq!(error, "/: missing required property \"age\"");
+    }
+
+    #[test]
+    fn rejects_additional_properties() {
+        let error = validate(&person_schema(), &json!({ "name": "Alice", "age": 30, "admin": true })).unwrap_err();
+        assert_eq!(error, "/admin: no value is allowed");
+
+        // additional properties must match the schema they are given
+        let schema = json!({ "additionalProperties": { "type": "string" } });
+        assert_eq!(validate(&schema, &json!({ "a": "b" })), Ok(()));
+        assert!(validate(&schema, &json!({ "a": 1 })).is_err());
+        // and are allowed by default
+        assert_eq!(validate(&json!({ "properties": {} }), &json!({ "a": 1 })), Ok(()));
+    }
+
+    #[test]
+    fn integer_type() {
+        let schema = json!({ "type": "integer" });
+        assert_eq!(validate(&schema, &json!(42)), Ok(()));
+        assert_eq!(validate(&schema, &json!(-42)), Ok(()));
+        assert_eq!(validate(&schema, &json!(u64::MAX)), Ok(()));
+        assert_eq!(validate(&schema, &json!(42.0)), Ok(()));
+        assert!(validate(&schema, &json!(42.5)).is_err());
+        assert!(validate(&schema, &json!("42")).is_err());
+        assert_eq!(validate(&json!({ "type": ["integer", "null"] }), &json!(null)), Ok(()));
+    }
+
+    #[test]
+    fn numeric_bounds() {
+        let schema = person_schema();
+        let person = |age: Value, score: Value| json!({ "name": "Alice", "age": age, "score": score });
+
+        // minimum and maximum are inclusive
+        assert_eq!(validate(&schema, &person(json!(0), json!(10))), Ok(()));
+        assert_eq!(validate(&schema, &person(json!(-1), json!(5))).unwrap_err(), "/age: number is out of range");
+        assert_eq!(validate(&schema, &person(json!(20), json!(10.5))).unwrap_err(), "/score: number is out of range");
+        // exclusive bounds are not
+        assert_eq!(validate(&schema, &person(json!(149), json!(0.1))), Ok(()));
+        assert_eq!(validate(&schema, &person(json!(150), json!(5))).unwrap_err(), "/age: number is out of range");
+        assert_eq!(validate(&schema, &person(json!(20), json!(0))).unwrap_err(), "/score: number is out of range");
+    }
+
+    #[test]
+    fn reports_nested_locations() {
+        let schema = json!({ "items": { "properties": { "a/b": { "const": 1 } } } });
+        let error = validate(&schema, &json!([{ "a/b": 1 }, { "a/b": 2 }])).unwrap_err();
+        assert_eq!(error, "/1/a~1b: value must be 1");
+    }
+
+    #[test]
+    fn rejects_unsupported_keywords() {
+        let error = validate(&json!({ "type": "string", "pattern": "^a" }), &json!("b")).unwrap_err();
+        assert_eq!(error, "schema /: unsupported keyword \"pattern\"");
+
+        // in subschemas too, whether or not the value has the property
+        let schema = json!({ "properties": { "a/b": { "items": { "anyOf": [] } } } });
+        let error = validate(&schema, &json!({})).unwrap_err();
+        assert_eq!(error, "schema /properties/a~1b/items: unsupported keyword \"anyOf\"");
+        let error = validate(&json!({ "additionalProperties": { "$ref": "#" } }), &json!({})).unwrap_err();
+        assert_eq!(error, "schema /additionalProperties: unsupported keyword \"$ref\"");
+
+        // annotations are allowed
+        let schema = json!({ "$schema": "https://json-schema.org/draft/2020-12/schema", "title": "Person", "description": "" });
+        assert_eq!(validate(&schema, &json!({})), Ok(()));
+    }
+}
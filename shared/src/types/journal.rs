@@ -0,0 +1,168 @@
+use std::fmt;
+use serde::{
+    Serialize, Deserialize, Serializer, Deserializer,
+    de::{self, SeqAccess, Visitor},
+};
+use super::{ScriptLang, ScriptLimits, ScriptValue, SchemaId, Challenge, ZkCommit, JOURNAL_VERSION};
+
+
+// Journal committed by the guest, in any of the formats it went through.
+// It is encoded as a (version, commit) tuple, so the version can be read before the commit.
+// Older versions were committed by older guests, whose receipts don't verify against the current image ID
+#[derive(Clone)]
+pub enum VersionedJournal {
+    V1(ZkCommitV1),
+    V2(ZkCommitV2),
+    V3(ZkCommitV3),
+    V4(ZkCommitV4),
+    V5(ZkCommit),
+}
+
+impl VersionedJournal {
+    pub fn version(&self) -> u32 {
+        match self {
+            VersionedJournal::V1(_) => 1,
+            VersionedJournal::V2(_) => 2,
+            VersionedJournal::V3(_) => 3,
+            VersionedJournal::V4(_) => 4,
+            VersionedJournal::V5(_) => 5,
+        }
+    }
+
+    /// Returns the commit of a journal of the current version, fails for older versions
+    pub fn into_current(self) -> Result<ZkCommit, String> {
+        match self {
+            VersionedJournal::V5(commit) => Ok(commit),
+            journal => Err(format!("Unsupported journal version {}, expected version {}", journal.version(), JOURNAL_VERSION)),
+        }
+    }
+}
+
+impl From<ZkCommit> for VersionedJournal {
+    fn from(commit: ZkCommit) -> Self {
+        VersionedJournal::V5(commit)
+    }
+}
+
+impl Serialize for VersionedJournal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            VersionedJournal::V1(commit) => (1u32, commit).serialize(serializer),
+            VersionedJournal::V2(commit) => (2u32, commit).serialize(serializer),
+            VersionedJournal::V3(commit) => (3u32, commit).serialize(serializer),
+            VersionedJournal::V4(commit) => (4u32, commit).serialize(serializer),
+            VersionedJournal::V5(commit) => (5u32, commit).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionedJournal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(2, JournalVisitor)
+    }
+}
+
+// Reads the version, then the commit in the format of that version
+struct JournalVisitor;
+
+impl<'de> Visitor<'de> for JournalVisitor {
+    type Value = VersionedJournal;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a journal version followed by a commit")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let version: u32 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let journal = match version {
+            1 => seq.next_element()?.map(VersionedJournal::V1),
+            2 => seq.next_element()?.map(VersionedJournal::V2),
+            3 => seq.next_element()?.map(VersionedJournal::V3),
+            4 => seq.next_element()?.map(VersionedJournal::V4),
+            5 => seq.next_element()?.map(VersionedJournal::V5),
+            _ => return Err(de::Error::custom(format!(
+                "unsupported journal version {}, expected version {} at most", version, JOURNAL_VERSION
+            ))),
+        };
+        journal.ok_or_else(|| de::Error::invalid_length(1, &self))
+    }
+}
+
+// Challenge of version 1 journals, issued without the verifier's time
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChallengeV1 {
+    pub nonce: String,
+    pub verifier_id: String,
+    pub expires_at: u64,
+    pub scope: Option<String>,
+}
+
+// Version 1: credentials are proven valid at the holder's time
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ZkCommitV1 {
+    pub has_error: bool,
+    pub err_msg: String,
+    pub cred_roots: Vec<String>,
+    pub revocation_roots: Vec<String>,
+    pub cred_schemas: Vec<SchemaId>,
+    pub cred_schema_hashes: Vec<String>,
+    pub lang: ScriptLang,
+    pub script: Option<String>,
+    pub script_hash: String,
+    pub challenge: ChallengeV1,
+    pub nullifier: Option<String>,
+    pub result: bool,
+}
+
+// Version 2: the challenge carries the verifier's time
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ZkCommitV2 {
+    pub has_error: bool,
+    pub err_msg: String,
+    pub cred_roots: Vec<String>,
+    pub revocation_roots: Vec<String>,
+    pub cred_schemas: Vec<SchemaId>,
+    pub cred_schema_hashes: Vec<String>,
+    pub lang: ScriptLang,
+    pub script: Option<String>,
+    pub script_hash: String,
+    pub challenge: Challenge,
+    pub nullifier: Option<String>,
+    pub result: bool,
+}
+
+// Version 3: the script limits are committed
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ZkCommitV3 {
+    pub has_error: bool,
+    pub err_msg: String,
+    pub cred_roots: Vec<String>,
+    pub revocation_roots: Vec<String>,
+    pub cred_schemas: Vec<SchemaId>,
+    pub cred_schema_hashes: Vec<String>,
+    pub lang: ScriptLang,
+    pub script: Option<String>,
+    pub script_hash: String,
+    pub limits: ScriptLimits,
+    pub challenge: Challenge,
+    pub nullifier: Option<String>,
+    pub result: bool,
+}
+
+// Version 4: scripts output any ScriptValue instead of a boolean
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ZkCommitV4 {
+    pub has_error: bool,
+    pub err_msg: String,
+    pub cred_roots: Vec<String>,
+    pub revocation_roots: Vec<String>,
+    pub cred_schemas: Vec<SchemaId>,
+    pub cred_schema_hashes: Vec<String>,
+    pub lang: ScriptLang,
+    pub script: Option<String>,
+    pub script_hash: String,
+    pub limits: ScriptLimits,
+    pub challenge: Challenge,
+    pub nullifier: Option<String>,
+    pub output: Option<ScriptValue>,
+}
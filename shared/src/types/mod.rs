@@ -1,4 +1,11 @@
+use std::collections::BTreeMap;
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::merkle::{MerklePath, NonMembershipProof};
+
+// Formats of the journal committed by the guest, past and current
+pub mod journal;
+pub use journal::VersionedJournal;
 
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -9,6 +16,16 @@ pub enum ScriptLang {
 
 pub type SchemaId = u32;
 
+// Kind of risc0 receipt produced by the prover
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ReceiptKind {
+    // one STARK per segment, largest but fastest to produce
+    #[default]
+    Composite,
+    // segments aggregated into a single STARK through recursion, constant size but slower to produce
+    Succinct,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CredentialInstanceData {
     pub details: String,
@@ -16,22 +33,173 @@ pub struct CredentialInstanceData {
     // why? shorter representation => faster parsing inside zkVM
     pub nonce: String,
     pub schema_id: SchemaId,
+    // base64 encoded sha256 hash of the secret known only to the holder
+    pub holder_commitment: String,
+    // UNIX timestamps (seconds) bounding when the credential can be proven, valid_until excluded
+    #[serde(default)]
+    pub valid_from: u64,
+    #[serde(default)]
+    pub valid_until: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize)]
+// Value returned by a script, and disclosed to the verifier
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum ScriptValue {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    // values of a map can't be maps themselves
+    Map(BTreeMap<String, ScriptValue>),
+}
+
+impl ScriptValue {
+    /// Converts a JSON value returned by a script. Fails for values that can't be disclosed (e.g. fractions, arrays)
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        Self::from_json_at_depth(value, 0)
+    }
+
+    fn from_json_at_depth(value: &Value, depth: usize) -> Result<Self, String> {
+        match value {
+            Value::Bool(boolean) => Ok(ScriptValue::Bool(*boolean)),
+            Value::Number(number) => number
+                .as_i64()
+                // JavaScript numbers are floats, even when they hold an integer
+                .or_else(|| number
+                    .as_f64()
+                    .filter(|float| float.fract() == 0.0 && (i64::MIN as f64..i64::MAX as f64).contains(float))
+                    .map(|float| float as i64)
+                )
+                .map(ScriptValue::Int)
+                .ok_or(format!("script output {} is not an integer", number)),
+            Value::String(string) => Ok(ScriptValue::Str(string.clone())),
+            Value::Object(object) if depth == 0 => object
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), Self::from_json_at_depth(value, depth + 1)?)))
+                .collect::<Result<_, String>>()
+                .map(ScriptValue::Map),
+            _ => Err("script output must be a boolean, an integer, a string, or a map of them".to_string()),
+        }
+    }
+}
+
+// Limits of the script engine, so a buggy or malicious script can't make the prover run forever.
+// When a limit is reached the script fails, and its error is committed like any other
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ScriptLimits {
+    // operations in Rhai, 0 for no limit. boa only limits iterations per loop, so it must be 0 for JavaScript
+    pub max_operations: u64,
+    // nested function calls, in both languages
+    pub max_call_depth: u32,
+    // bytes in a string, 0 for no limit. Only enforced in Rhai, so it must be 0 for JavaScript
+    pub max_string_size: u32,
+    // items in an array or object, 0 for no limit. Only enforced in Rhai, so it must be 0 for JavaScript
+    pub max_collection_size: u32,
+}
+
+impl ScriptLimits {
+    /// Default limits of the given language's engine
+    pub fn default_for(lang: ScriptLang) -> Self {
+        match lang {
+            ScriptLang::Rhai => Self::default(),
+            // boa can't limit the total operations, or the size of strings and collections.
+            // JavaScript scripts are only bounded by the cycle budget
+            ScriptLang::JavaScript => Self { max_operations: 0, max_string_size: 0, max_collection_size: 0, ..Self::default() },
+        }
+    }
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 1_000_000,
+            max_call_depth: 32,
+            max_string_size: 64 * 1024,
+            max_collection_size: 10_000,
+        }
+    }
+}
+
+// Issued by a verifier and bound to a single presentation, to prevent replaying receipts
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Challenge {
+    // base64 encoded random integer (u128)
+    pub nonce: String,
+    pub verifier_id: String,
+    // UNIX timestamp (seconds) of the verifier's clock when issuing the challenge,
+    // credentials are proven valid at this time
+    pub issued_at: u64,
+    // UNIX timestamp (seconds) after which the verifier rejects the challenge
+    pub expires_at: u64,
+    // if set, the proof must include the holder's nullifier for this scope (e.g. "vote-2024")
+    pub scope: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ZkvmInput {
     pub credentials: Vec<String>,
+    // inclusion proof of each credential's hash in its issuer's registry tree
+    pub cred_paths: Vec<MerklePath>,
+    // proof that each credential's hash is not in its issuer's revocation tree
+    pub cred_revocations: Vec<NonMembershipProof>,
+    // JSON Schema of each credential, as registered by its issuer
+    pub cred_schema_docs: Vec<String>,
     pub lang: ScriptLang,
     pub script: String,
+    pub limits: ScriptLimits,
+    // JSON pointers (e.g. "/address/country") of the fields to disclose verbatim, per credential
+    pub disclose: Vec<Vec<String>>,
+    // only commit the hash of the script, which is then given to the verifier out-of-band
+    pub script_hash_only: bool,
+    pub challenge: Challenge,
+    // proves the credentials were issued to the prover (never committed)
+    pub holder_secret: String,
 }
 
+// Version of the journal format. The guest commits it before the ZkCommit, as a VersionedJournal.
+// It must be bumped on every change to ZkCommit, keeping the previous format in the journal module,
+// so old receipts are detected instead of misread
+pub const JOURNAL_VERSION: u32 = 5;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ZkCommit {
     pub has_error: bool,
     pub err_msg: String,
-    pub cred_hashes: Vec<String>,
+    // base64 encoded registry roots the credentials are included in (credential hashes stay private)
+    pub cred_roots: Vec<String>,
+    // base64 encoded revocation roots the credentials are proven absent from
+    pub revocation_roots: Vec<String>,
     pub cred_schemas: Vec<SchemaId>,
+    // base64 encoded hashes of the schemas the credentials' details were validated against
+    pub cred_schema_hashes: Vec<String>,
     pub lang: ScriptLang,
-    pub script: String,
-    pub result: bool,
+    // None if the holder chose to only commit the script hash
+    pub script: Option<String>,
+    // base64 encoded sha256 of the script
+    pub script_hash: String,
+    // limits the script was run with
+    pub limits: ScriptLimits,
+    pub challenge: Challenge,
+    // H(holder_secret || verifier_id/scope): the same for all presentations of a holder under a challenge scope,
+    // but unlinkable across verifiers and scopes. Only set for scoped challenges
+    pub nullifier: Option<String>,
+    // value returned by the script, None if it failed
+    pub output: Option<ScriptValue>,
+    // per credential, map JSON pointer => JSON encoded value of the fields disclosed by the holder
+    pub disclosed: Vec<BTreeMap<String, String>>,
+}
+
+// Request to a remote proving service
+#[derive(Serialize, Deserialize)]
+pub struct RemoteProofRequest {
+    pub input: ZkvmInput,
+    pub receipt_kind: ReceiptKind,
+}
+
+// Response of a remote proving service, with either a receipt or an error
+#[derive(Serialize, Deserialize)]
+pub struct RemoteProofResponse {
+    // Base64 encoded bincode of the risc0 Receipt
+    pub base64_receipt: Option<String>,
+    pub error: Option<String>,
+    pub prove_ms: u64,
 }